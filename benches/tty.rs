@@ -0,0 +1,61 @@
+//! Compares the shared controlling terminal descriptor against reopening `/dev/tty` per call.
+//!
+//! Run with `cargo bench --bench tty` from a terminal.
+//!
+//! The savings in syscalls show under `strace`. Build the benchmark with
+//! `cargo bench --bench tty --no-run`, which prints the path of the executable, and run each
+//! variant on its own:
+//!
+//! ```text
+//! strace -c -e trace=openat,ioctl,close target/release/deps/tty-<hash> shared
+//! strace -c -e trace=openat,ioctl,close target/release/deps/tty-<hash> reopened
+//! ```
+//!
+//! Compare the `calls` column. The shared descriptor makes one `ioctl` per call, so about
+//! 100000 in total, while reopening adds an `openat` and a `close` to every call.
+
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 100_000;
+
+fn main() {
+    if let Err(err) = terminal_utils::size() {
+        eprintln!("skipping benchmark, no terminal available: {err}");
+        return;
+    }
+
+    // Without a variant, as under `cargo bench`, both run.
+    let variant = env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let runs = |name: &str| variant.is_none() || variant.as_deref() == Some(name);
+
+    // shared descriptor: one ioctl per call
+    if runs("shared") {
+        report("shared", measure(|| terminal_utils::size().unwrap()));
+    }
+
+    // reopened descriptor: open, ioctl and close per call
+    #[cfg(unix)]
+    if runs("reopened") {
+        let reopened = measure(|| {
+            terminal_utils::Terminal::open("/dev/tty")
+                .unwrap()
+                .size()
+                .unwrap()
+        });
+        report("reopened", reopened);
+    }
+}
+
+fn measure<T>(f: impl Fn() -> T) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!("{name:<10} {:>10.0?} per call", elapsed / ITERATIONS);
+}
@@ -0,0 +1,270 @@
+//! Records terminal sessions in the [asciicast v2] format of asciinema.
+//!
+//! A recording starts with a header line and continues with one line per event, each with the
+//! seconds since the start: output of the program (`o`), input typed by the user (`i`) and
+//! resizes (`r`). Input is only recorded when asked for, because it contains passwords typed
+//! without echo.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::process::Command;
+//!
+//! let file = File::create("session.cast").unwrap();
+//! let status = terminal_utils::asciicast::record(Command::new("sh"), file).unwrap();
+//! println!("the shell exited with {status}");
+//! ```
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fmt, mem};
+
+use crate::passthrough::Passthrough;
+use crate::{ResizeSubscription, Terminal, TerminalSize};
+
+/// Runs `command` in a pass-through session on the controlling terminal, records its output
+/// and resizes to `writer` and returns its exit status.
+pub fn record<W: Write + Send + 'static>(
+    command: Command,
+    writer: W,
+) -> Result<ExitStatus, io::Error> {
+    let terminal = Terminal::tty()?;
+    let recorder = Recorder::new(writer, terminal.size()?)?;
+
+    let subscription = recorder.record_resizes(&terminal)?;
+    let status = recorder
+        .attach(Passthrough::new(command))
+        .run_on(&terminal)?;
+    drop(subscription);
+
+    recorder.finish()?;
+    Ok(status)
+}
+
+/// Writes an asciicast v2 recording to a writer.
+///
+/// Events can be recorded directly, from a [`Passthrough`] session with
+/// [`Recorder::attach`] and from resize notifications with [`Recorder::record_resizes`].
+/// Errors of the writer while recording through hooks are kept and returned by
+/// [`Recorder::finish`].
+pub struct Recorder<W> {
+    state: Arc<Mutex<State<W>>>,
+    record_input: bool,
+}
+
+struct State<W> {
+    writer: W,
+    start: Instant,
+    /// The start of a UTF-8 sequence that was split across reads, per stream.
+    pending_output: Vec<u8>,
+    pending_input: Vec<u8>,
+    error: Option<io::Error>,
+}
+
+impl<W> fmt::Debug for Recorder<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder")
+            .field("record_input", &self.record_input)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W: Write + Send + 'static> Recorder<W> {
+    /// Starts a recording of a terminal of the given size by writing the header.
+    ///
+    /// The header includes the current time and the `SHELL` and `TERM` environment variables.
+    pub fn new(mut writer: W, size: TerminalSize) -> Result<Self, io::Error> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let mut env = String::new();
+        for name in ["SHELL", "TERM"] {
+            if let Ok(value) = env::var(name) {
+                let separator = if env.is_empty() { "" } else { ", " };
+                write!(
+                    env,
+                    "{separator}{}: {}",
+                    json_string(name),
+                    json_string(&value)
+                )
+                .unwrap();
+            }
+        }
+        writeln!(
+            writer,
+            r#"{{"version": 2, "width": {}, "height": {}, "timestamp": {timestamp}, "env": {{{env}}}}}"#,
+            size.width, size.height,
+        )?;
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(State {
+                writer,
+                start: Instant::now(),
+                pending_output: Vec::new(),
+                pending_input: Vec::new(),
+                error: None,
+            })),
+            record_input: false,
+        })
+    }
+
+    /// Records the input of attached sessions as well. Defaults to `false`.
+    pub fn record_input(mut self, record_input: bool) -> Self {
+        self.record_input = record_input;
+        self
+    }
+
+    /// Records output of the program.
+    pub fn output(&self, bytes: &[u8]) -> Result<(), io::Error> {
+        let mut state = lock(&self.state);
+        let data = take_utf8(&mut state.pending_output, bytes);
+        state.event("o", &data)
+    }
+
+    /// Records input typed by the user.
+    pub fn input(&self, bytes: &[u8]) -> Result<(), io::Error> {
+        let mut state = lock(&self.state);
+        let data = take_utf8(&mut state.pending_input, bytes);
+        state.event("i", &data)
+    }
+
+    /// Records a resize of the terminal.
+    pub fn resize(&self, size: TerminalSize) -> Result<(), io::Error> {
+        lock(&self.state).event("r", &resize_data(size))
+    }
+
+    /// Records the resizes of `terminal` until the returned subscription is dropped.
+    pub fn record_resizes(&self, terminal: &Terminal) -> Result<ResizeSubscription, io::Error> {
+        let state = self.state.clone();
+        terminal.on_resize_callback(move |size| {
+            lock(&state).record("r", &resize_data(size));
+        })
+    }
+
+    /// Installs hooks in `passthrough` that record the output and, if enabled, the input of
+    /// the session. Hooks installed afterwards replace them.
+    pub fn attach(&self, passthrough: Passthrough) -> Passthrough {
+        let state = self.state.clone();
+        let passthrough = passthrough.on_output(move |bytes| {
+            let mut state = lock(&state);
+            let data = take_utf8(&mut state.pending_output, bytes);
+            state.record("o", &data);
+        });
+        if !self.record_input {
+            return passthrough;
+        }
+
+        let state = self.state.clone();
+        passthrough.on_input(move |bytes| {
+            let mut state = lock(&state);
+            let data = take_utf8(&mut state.pending_input, bytes);
+            state.record("i", &data);
+        })
+    }
+
+    /// Records what is left of an unfinished UTF-8 sequence, flushes the writer and returns
+    /// it, or the first error that occurred while recording through hooks. Fails while
+    /// attached sessions or resize subscriptions still use the recorder.
+    pub fn finish(self) -> Result<W, io::Error> {
+        let mut state = Arc::try_unwrap(self.state)
+            .map_err(|_| io::Error::other("the recorder is still in use"))?
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(err) = state.error {
+            return Err(err);
+        }
+
+        let output = String::from_utf8_lossy(&mem::take(&mut state.pending_output)).into_owned();
+        state.event("o", &output)?;
+        let input = String::from_utf8_lossy(&mem::take(&mut state.pending_input)).into_owned();
+        state.event("i", &input)?;
+
+        let mut writer = state.writer;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> State<W> {
+    fn event(&mut self, code: &str, data: &str) -> Result<(), io::Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let time = self.start.elapsed().as_secs_f64();
+        writeln!(
+            self.writer,
+            "[{time:.6}, \"{code}\", {}]",
+            json_string(data)
+        )
+    }
+
+    /// Records an event from a hook, keeping the first error for [`Recorder::finish`].
+    fn record(&mut self, code: &str, data: &str) {
+        if self.error.is_none() {
+            if let Err(err) = self.event(code, data) {
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+/// Decodes `bytes` after the bytes left over from the last call, keeping an incomplete
+/// sequence at the end for the next call. Invalid bytes become replacement characters.
+fn take_utf8(pending: &mut Vec<u8>, bytes: &[u8]) -> String {
+    pending.extend_from_slice(bytes);
+    let complete = pending.len() - incomplete_suffix(pending);
+
+    let data = String::from_utf8_lossy(&pending[..complete]).into_owned();
+    pending.drain(..complete);
+    data
+}
+
+/// Returns the length of the UTF-8 sequence that was started but not finished at the end.
+fn incomplete_suffix(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - back];
+        // continuation bytes start with 0b10
+        if byte & 0xc0 != 0x80 {
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            return if len > back { back } else { 0 };
+        }
+    }
+
+    0
+}
+
+fn resize_data(size: TerminalSize) -> String {
+    format!("{}x{}", size.width, size.height)
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,121 @@
+//! Reads from and writes to terminals under tokio.
+
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{sys, PtyMaster, Terminal};
+
+/// Nonblocking reads and writes on a terminal or the master of a pseudo-terminal.
+/// Created with [`Terminal::to_async`] and [`PtyMaster::into_async`].
+///
+/// Once the other side hangs up, for example because the program on a pseudo-terminal exited,
+/// reads report the end of the file instead of failing with `EIO`.
+#[derive(Debug)]
+pub struct AsyncTerminal {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl AsyncTerminal {
+    /// Registers `fd` with the tokio reactor of the current runtime.
+    pub(crate) fn new(fd: OwnedFd) -> Result<Self, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd())?;
+
+        Ok(Self {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+}
+
+impl AsyncRead for AsyncTerminal {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            let result = guard.try_io(|fd| {
+                let read = unsafe {
+                    libc::read(fd.as_raw_fd(), unfilled.as_mut_ptr().cast(), unfilled.len())
+                };
+                if read < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(read as usize)
+            });
+
+            match result {
+                Ok(Ok(read)) => {
+                    buf.advance(read);
+                    return Poll::Ready(Ok(()));
+                }
+                // the slave side of a pseudo-terminal was closed or the terminal hung up
+                Ok(Err(err)) if err.raw_os_error() == Some(libc::EIO) => {
+                    return Poll::Ready(Ok(()))
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AsyncTerminal {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+            let result = guard.try_io(|fd| {
+                let written =
+                    unsafe { libc::write(fd.as_raw_fd(), buf.as_ptr().cast(), buf.len()) };
+                if written < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(written as usize)
+            });
+
+            match result {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsFd for AsyncTerminal {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.get_ref().as_fd()
+    }
+}
+
+impl AsRawFd for AsyncTerminal {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+pub(crate) fn from_terminal(terminal: &Terminal) -> Result<AsyncTerminal, io::Error> {
+    // A descriptor of its own keeps the nonblocking flag away from other users of the terminal.
+    AsyncTerminal::new(terminal.handle.reopen()?)
+}
+
+pub(crate) fn from_master(master: PtyMaster) -> Result<AsyncTerminal, io::Error> {
+    AsyncTerminal::new(OwnedFd::from(master))
+}
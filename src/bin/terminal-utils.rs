@@ -0,0 +1,65 @@
+//! Command line companion of the `terminal-utils` crate.
+//!
+//! ```text
+//! terminal-utils recover [TTY]   restore the state saved by a process that died in raw mode
+//! terminal-utils show [TTY]      print the current settings in the style of `stty -a`
+//! ```
+//!
+//! Without `TTY`, the controlling terminal is used.
+
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: terminal-utils recover [TTY]   restore the state saved by a process that died in raw mode
+       terminal-utils show [TTY]      print the current settings in the style of `stty -a`";
+
+#[cfg(unix)]
+fn main() -> ExitCode {
+    use std::io;
+
+    use terminal_utils::Terminal;
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, path) = match args.as_slice() {
+        [command] if is_command(command) => (command.as_str(), None),
+        [command, path] if is_command(command) => (command.as_str(), Some(path)),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let terminal = match path {
+        Some(path) => Terminal::open(path),
+        None => Terminal::tty(),
+    };
+    let result = terminal.and_then(|terminal| match command {
+        "recover" => terminal.recover().map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => {
+                io::Error::new(err.kind(), "no saved state for this terminal")
+            }
+            _ => err,
+        }),
+        _ => terminal.snapshot().map(|snapshot| print!("{snapshot}")),
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("terminal-utils: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_command(command: &str) -> bool {
+    matches!(command, "recover" | "show")
+}
+
+#[cfg(not(unix))]
+fn main() -> ExitCode {
+    eprintln!("{USAGE}");
+    eprintln!("terminal-utils: only supported on Unix");
+    ExitCode::FAILURE
+}
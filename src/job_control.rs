@@ -0,0 +1,121 @@
+use std::io;
+
+#[cfg(feature = "tokio")]
+use std::thread;
+
+#[cfg(feature = "tokio")]
+use tokio::sync::watch;
+
+use crate::{sys, RawModeGuard};
+#[cfg(feature = "tokio")]
+use crate::{Terminal, TerminalSize};
+
+/// A raw mode guard that survives suspending the process with `Ctrl-Z` and resuming it
+/// with `fg`. Created with [`RawModeGuard::with_job_control`].
+///
+/// When the process receives `SIGTSTP`, the previous mode is restored and the signal is
+/// raised again, so the process stops and the shell is usable. Once the process continues,
+/// raw mode is enabled again. With the `tokio` feature, [`JobControlGuard::on_resume`]
+/// receivers are notified with the current terminal size, so the application can redraw.
+/// The notifications are sent from a thread of this crate, so no tokio runtime is needed.
+///
+/// Dropping the guard reinstates the previous `SIGTSTP` handler and restores the previous mode.
+///
+/// ```no_run
+/// # #[cfg(feature = "tokio")] {
+/// let guard = terminal_utils::RawModeOptions::new()
+///     .signals(true)
+///     .enable()
+///     .unwrap()
+///     .with_job_control()
+///     .unwrap();
+///
+/// let mut resume_rx = guard.on_resume();
+/// tokio::spawn(async move {
+///     loop {
+///         resume_rx.changed().await.unwrap();
+///
+///         let size = resume_rx.borrow();
+///         println!("resumed, redrawing at {:?}", size);
+///     }
+/// });
+/// # }
+/// ```
+pub struct JobControlGuard {
+    _guard: RawModeGuard,
+    #[cfg(feature = "tokio")]
+    resume_rx: watch::Receiver<TerminalSize>,
+}
+
+impl RawModeGuard {
+    /// Keeps raw mode working across suspend and resume of the process.
+    /// Only one guard with job control can exist at a time.
+    pub fn with_job_control(self) -> Result<JobControlGuard, io::Error> {
+        let handle = &self.guard.terminal.handle;
+        let raw_state = sys::current_state(handle)?;
+
+        let original_state = self.guard.outermost_state();
+        sys::install_suspend_handler(handle.raw(), original_state, raw_state)?;
+
+        #[cfg(feature = "tokio")]
+        let resume_rx = match spawn_resume_thread(&self.guard.terminal) {
+            Ok(resume_rx) => resume_rx,
+            Err(err) => {
+                let _ = sys::uninstall_resume_handler();
+                let _ = sys::uninstall_suspend_handler();
+                return Err(err);
+            }
+        };
+
+        Ok(JobControlGuard {
+            _guard: self,
+            #[cfg(feature = "tokio")]
+            resume_rx,
+        })
+    }
+}
+
+impl JobControlGuard {
+    /// Returns a receiver that receives the terminal size whenever the process resumes.
+    #[cfg(feature = "tokio")]
+    pub fn on_resume(&self) -> watch::Receiver<TerminalSize> {
+        self.resume_rx.clone()
+    }
+
+    /// Suspends the process like `Ctrl-Z` would.
+    ///
+    /// This is useful in raw mode without [`crate::RawModeOptions::signals`], where `Ctrl-Z`
+    /// is read as a regular byte instead of stopping the process.
+    pub fn suspend(&self) -> Result<(), io::Error> {
+        sys::suspend()
+    }
+}
+
+impl Drop for JobControlGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "tokio")]
+        let _ = sys::uninstall_resume_handler();
+        let _ = sys::uninstall_suspend_handler();
+    }
+}
+
+/// Spawns a thread that sends the terminal size whenever the process continues. It ends
+/// once [`sys::uninstall_resume_handler`] closes the pipe it waits on.
+#[cfg(feature = "tokio")]
+fn spawn_resume_thread(terminal: &Terminal) -> Result<watch::Receiver<TerminalSize>, io::Error> {
+    let terminal = terminal.try_clone()?;
+    let (tx, resume_rx) = watch::channel(terminal.size()?);
+    let resumed = sys::install_resume_handler()?;
+
+    thread::Builder::new()
+        .name("terminal-utils-resume".into())
+        .spawn(move || {
+            while resumed.wait().is_ok() {
+                if let Ok(size) = terminal.size() {
+                    tx.send_replace(size);
+                }
+            }
+        })?;
+
+    Ok(resume_rx)
+}
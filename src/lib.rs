@@ -8,6 +8,25 @@
 //! println!("The terminal is {}x{} characters.", size.width, size.height);
 //! ```
 //!
+//! Without a controlling terminal, for example in cron jobs, containers or CI, `size_with_fallback`
+//! tries the standard streams and the `COLUMNS` and `LINES` variables before using a default.
+//!
+//! ```
+//! use terminal_utils::TerminalSize;
+//!
+//! let default = TerminalSize { width: 80, height: 24, pixel_width: 0, pixel_height: 0 };
+//! let (size, source) = terminal_utils::size_with_fallback(default);
+//! println!("{}x{} from {:?}", size.width, size.height, source);
+//! ```
+//!
+//! Many terminals do not report their size in pixels to the operating system. `query_pixel_size`
+//! asks the terminal itself, which is useful for laying out images.
+//!
+//! ```
+//! let size = terminal_utils::query_pixel_size(std::time::Duration::from_millis(100)).unwrap();
+//! println!("Cells are {:?}x{:?} pixels.", size.cell_width_px(), size.cell_height_px());
+//! ```
+//!
 //! ## Raw mode
 //!
 //! ```
@@ -22,8 +41,149 @@
 //! println!("Raw mode is disabled.");
 //! ```
 //!
+//! ## Raw mode options
+//!
+//! ```
+//! let raw_mode_guard = terminal_utils::RawModeOptions::new()
+//!     .signals(true)
+//!     .output_processing(true)
+//!     .enable()
+//!     .unwrap();
+//! println!("Raw mode is enabled, but Ctrl-C still sends SIGINT.");
+//!
+//! drop(raw_mode_guard);
+//! ```
+//!
+//! ## Cbreak and no-echo mode
+//!
+//! ```
+//! use terminal_utils::TerminalMode;
+//!
+//! let cbreak_mode_guard = terminal_utils::enable_cbreak_mode().unwrap();
+//! assert_eq!(terminal_utils::current_mode().unwrap(), TerminalMode::Cbreak);
+//! drop(cbreak_mode_guard);
+//!
+//! let no_echo_guard = terminal_utils::enable_no_echo_mode().unwrap();
+//! assert_eq!(terminal_utils::current_mode().unwrap(), TerminalMode::NoEcho);
+//! drop(no_echo_guard);
+//! ```
+//!
+//! ## Terminal handle
+//!
+//! The free functions always use the controlling terminal. A [`Terminal`] handle can target
+//! any other terminal on Unix, for example a pseudo-terminal that is owned by the process.
+//!
+//! ```no_run
+//! let terminal = terminal_utils::Terminal::open("/dev/pts/7").unwrap();
+//! println!("{:?}", terminal.size().unwrap());
+//!
+//! let raw_mode_guard = terminal.enable_raw_mode().unwrap();
+//! assert!(terminal.is_raw_mode_enabled().unwrap());
+//! drop(raw_mode_guard);
+//! ```
+//!
+//! ## Nested guards
+//!
+//! Guards of the same terminal form a stack. The terminal only returns to its original mode once
+//! the last guard is dropped, even if the guards are dropped in a different order than they
+//! were created in.
+//!
+//! ```
+//! use terminal_utils::TerminalMode;
+//!
+//! let cbreak_mode_guard = terminal_utils::enable_cbreak_mode().unwrap();
+//! let raw_mode_guard = terminal_utils::enable_raw_mode().unwrap();
+//!
+//! // Dropped out of order, the terminal stays in raw mode.
+//! drop(cbreak_mode_guard);
+//! assert_eq!(terminal_utils::current_mode().unwrap(), TerminalMode::Raw);
+//!
+//! drop(raw_mode_guard);
+//! assert_eq!(terminal_utils::current_mode().unwrap(), TerminalMode::Normal);
+//! ```
+//!
+//! ## Restore on panic and signals
+//!
+//! Mode guards restore the terminal when they are dropped, which does not happen on `abort`,
+//! `process::exit` or when a signal kills the process. Installing the restore hooks covers
+//! those cases as well. Panics only restore the terminal when they end the process, so a
+//! panicking worker thread does not reset the mode under the rest of the program.
+//!
+//! ```
+//! terminal_utils::install_restore_hooks().unwrap();
+//!
+//! let raw_mode_guard = terminal_utils::enable_raw_mode().unwrap();
+//! // A panic of the main thread, `process::exit` or `SIGTERM` from here on restores the
+//! // previous mode first.
+//! drop(raw_mode_guard);
+//! ```
+//!
+//! ## Recover after a crash
+//!
+//! A process killed with `SIGKILL` has no chance to restore the terminal. On Unix, the original
+//! state can be saved to a file whenever raw mode is enabled, and reapplied later with
+//! `recover_terminal` or by running `terminal-utils recover` on the same terminal.
+//!
+//! ```
+//! terminal_utils::set_persist_state(true);
+//!
+//! let raw_mode_guard = terminal_utils::enable_raw_mode().unwrap();
+//! drop(raw_mode_guard);
+//! ```
+//!
+//! ## Job control
+//!
+//! On Unix, a raw mode guard can restore the previous mode when the process is suspended with
+//! `Ctrl-Z` and enable raw mode again after `fg`. With the `tokio` feature, the application is
+//! notified when it resumes, so it can redraw.
+//!
+//! ```no_run
+//! # #[cfg(feature = "tokio")] {
+//! let guard = terminal_utils::enable_raw_mode()
+//!     .unwrap()
+//!     .with_job_control()
+//!     .unwrap();
+//!
+//! let mut resume_rx = guard.on_resume();
+//! tokio::spawn(async move {
+//!     loop {
+//!         resume_rx.changed().await.unwrap();
+//!         println!("resumed, redrawing at {:?}", resume_rx.borrow());
+//!     }
+//! });
+//! # }
+//! ```
+//!
+//! ## Terminal settings
+//!
+//! On Unix, a snapshot of the terminal settings can be printed in the style of `stty -a`, parsed
+//! back and compared with another snapshot, which helps to find out what a program changed.
+//!
+//! ```
+//! let terminal = terminal_utils::Terminal::tty().unwrap();
+//! let before = terminal.snapshot().unwrap();
+//!
+//! let raw_mode_guard = terminal.enable_raw_mode().unwrap();
+//! for change in before.diff(&terminal.snapshot().unwrap()) {
+//!     println!("raw mode changed {change}");
+//! }
+//! drop(raw_mode_guard);
+//! ```
+//!
 //! ## Resize signal
-//! This feature is only available with the `tokio` feature. It is enabled by default.
+//!
+//! Resizes can be received without an async runtime, through a channel, a callback or, on Unix,
+//! a file descriptor that becomes readable. `on_resize_debounced` waits for the size to settle
+//! and reports the old and new size.
+//!
+//! ```no_run
+//! for size in terminal_utils::resize_channel().unwrap() {
+//!     println!("terminal size changed: {:?}", size);
+//! }
+//! ```
+//!
+//! With the `tokio` feature, which is enabled by default, resizes are also available as a watch
+//! channel.
 //!
 //! ```no_run
 //! let mut resize_rx = terminal_utils::on_resize().unwrap();
@@ -36,19 +196,97 @@
 //!     }
 //! });
 //! ```
+//!
+//! The `futures` feature adds `resize_stream`, a `Stream` of sizes that works with any async
+//! runtime.
+//!
+//! On Unix, the `async-io` feature adds `on_resize_async` for smol, async-std and other
+//! runtimes built on `async-io`.
+//!
+//! ## Pseudo-terminals
+//!
+//! On Unix, `openpty` creates a pseudo-terminal to host another program. The slave is a
+//! `Terminal`, so its size and mode are managed like those of any other terminal, while the
+//! program's output is read from the master. `PtyCommand` runs a
+//! `std::process::Command` on a new pseudo-terminal that becomes its controlling terminal.
+//! With the `tokio` feature, `PtyMaster::into_async` and `Terminal::to_async` provide
+//! `AsyncRead` and `AsyncWrite`.
+//! `ResizeForwarding` keeps the size of a pseudo-terminal in line with the terminal it is
+//! shown in.
+//! `passthrough::run` combines all of this into a `script(1)`-style session, and
+//! `asciicast::record` records such a session for asciinema.
+//!
+//! ```
+//! use std::io::Read;
+//!
+//! let size = terminal_utils::size().unwrap();
+//! let mut pty = terminal_utils::openpty(size, None).unwrap();
+//! let _raw_mode_guard = pty.slave.enable_raw_mode().unwrap();
+//!
+//! let mut output = [0; 1024];
+//! match pty.master.read(&mut output) {
+//!     Ok(n) => println!("read {n} bytes"),
+//!     Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => println!("no output yet"),
+//!     Err(err) => panic!("{err}"),
+//! }
+//! ```
 
+#[cfg(unix)]
+pub mod asciicast;
+#[cfg(all(unix, feature = "tokio"))]
+mod async_terminal;
+#[cfg(unix)]
+mod job_control;
+#[cfg(unix)]
+pub mod passthrough;
+#[cfg(unix)]
+mod pty;
+mod query;
+#[cfg(unix)]
+mod recovery;
+mod resize;
+mod restore;
+mod size;
+#[cfg(unix)]
+mod snapshot;
+mod stack;
+mod terminal;
 #[cfg(unix)]
 mod unix;
 #[cfg(windows)]
 mod windows;
 
 use std::io;
+use std::time::Duration;
 
 #[cfg(unix)]
 use unix as sys;
 #[cfg(windows)]
 use windows as sys;
 
+#[cfg(all(unix, feature = "tokio"))]
+pub use async_terminal::AsyncTerminal;
+#[cfg(unix)]
+pub use job_control::JobControlGuard;
+#[cfg(unix)]
+pub use pty::{openpty, Pty, PtyChild, PtyCommand, PtyMaster, ResizeForwarding};
+#[cfg(unix)]
+pub use recovery::set_persist_state;
+#[cfg(all(unix, feature = "async-io"))]
+pub use resize::AsyncResizeReceiver;
+#[cfg(unix)]
+pub use resize::ResizeFd;
+#[cfg(feature = "futures")]
+pub use resize::ResizeStream;
+pub use resize::{ResizeChannel, ResizeEvent, ResizeSubscription};
+pub use restore::install_restore_hooks;
+pub use size::{size_with_fallback, SizeSource};
+#[cfg(unix)]
+pub use snapshot::{SnapshotChange, TermiosSnapshot};
+pub use stack::set_out_of_order_hook;
+pub use sys::TerminalState;
+pub use terminal::Terminal;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TerminalSize {
     pub width: u16,
@@ -58,49 +296,357 @@ pub struct TerminalSize {
     pub pixel_height: u16,
 }
 
+impl TerminalSize {
+    /// Returns the width of a cell in pixels, or `None` if the pixel size is unknown.
+    pub fn cell_width_px(&self) -> Option<u16> {
+        (self.pixel_width > 0 && self.width > 0).then(|| self.pixel_width / self.width)
+    }
+
+    /// Returns the height of a cell in pixels, or `None` if the pixel size is unknown.
+    pub fn cell_height_px(&self) -> Option<u16> {
+        (self.pixel_height > 0 && self.height > 0).then(|| self.pixel_height / self.height)
+    }
+}
+
 /// Returns the size of the terminal.
 pub fn size() -> Result<TerminalSize, io::Error> {
-    sys::size()
+    Terminal::tty()?.size()
+}
+
+/// Returns the size of the terminal, asking the terminal for the pixel size if the operating
+/// system does not know it. See [`Terminal::query_pixel_size`].
+pub fn query_pixel_size(timeout: Duration) -> Result<TerminalSize, io::Error> {
+    Terminal::tty()?.query_pixel_size(timeout)
+}
+
+/// The input mode a terminal is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalMode {
+    /// Input is line buffered and echoed, the default mode of a terminal.
+    Normal,
+    /// Input is passed through unprocessed, without echo and without generating signals.
+    Raw,
+    /// Input is available per key press without echo, but signals are still generated.
+    Cbreak,
+    /// Input is line buffered, but typed characters are not echoed.
+    NoEcho,
+}
+
+/// Returns the mode the terminal is currently in.
+pub fn current_mode() -> Result<TerminalMode, io::Error> {
+    Terminal::tty()?.current_mode()
 }
 
 /// Tells whether the raw mode is currently enabled.
 pub fn is_raw_mode_enabled() -> Result<bool, io::Error> {
-    sys::is_raw_mode_enabled()
+    Ok(current_mode()? == TerminalMode::Raw)
 }
 
 /// Enables raw mode.
 /// Once the returned guard is dropped, the previous mode is restored.
 pub fn enable_raw_mode() -> Result<RawModeGuard, io::Error> {
-    RawModeGuard::new()
+    RawModeOptions::new().enable()
+}
+
+/// Enables cbreak mode, which turns off line buffering and echo but keeps signals
+/// and output processing.
+/// Once the returned guard is dropped, the previous mode is restored.
+pub fn enable_cbreak_mode() -> Result<CbreakModeGuard, io::Error> {
+    Terminal::tty()?.enable_cbreak_mode()
+}
+
+/// Enables no-echo mode, which keeps line buffering but stops echoing typed characters.
+/// This is useful for reading passwords and other secrets.
+/// Once the returned guard is dropped, the previous mode is restored.
+pub fn enable_no_echo_mode() -> Result<NoEchoGuard, io::Error> {
+    Terminal::tty()?.enable_no_echo_mode()
+}
+
+/// Restores the controlling terminal to the state saved by a process that died in raw mode.
+/// See [`set_persist_state`].
+#[cfg(unix)]
+pub fn recover_terminal() -> Result<(), io::Error> {
+    Terminal::tty()?.recover()
 }
 
-/// Returns a receiver that receives a signal when the terminal is resized.
+/// Calls `callback` with the new size whenever the terminal is resized, until the returned
+/// subscription is dropped. See [`Terminal::on_resize_callback`].
+pub fn on_resize_callback(
+    callback: impl FnMut(TerminalSize) + Send + 'static,
+) -> Result<ResizeSubscription, io::Error> {
+    Terminal::tty()?.on_resize_callback(callback)
+}
+
+/// Returns a channel that receives the new size whenever the terminal is resized.
+/// See [`Terminal::resize_channel`].
+pub fn resize_channel() -> Result<ResizeChannel<TerminalSize>, io::Error> {
+    Terminal::tty()?.resize_channel()
+}
+
+/// Returns a channel that receives an event once the terminal size settles after a burst of
+/// resizes. See [`Terminal::on_resize_debounced`].
+pub fn on_resize_debounced(delay: Duration) -> Result<ResizeChannel<ResizeEvent>, io::Error> {
+    Terminal::tty()?.on_resize_debounced(delay)
+}
+
+/// Returns a stream of the new sizes of the terminal. See [`Terminal::resize_stream`].
+#[cfg(feature = "futures")]
+pub fn resize_stream() -> Result<ResizeStream, io::Error> {
+    Terminal::tty()?.resize_stream()
+}
+
+/// Returns a file descriptor that becomes readable whenever the terminal is resized.
+/// See [`Terminal::resize_fd`].
+#[cfg(unix)]
+pub fn resize_fd() -> Result<ResizeFd, io::Error> {
+    Terminal::tty()?.resize_fd()
+}
+
+/// Returns a receiver that receives the new size when the terminal is resized.
+/// See [`Terminal::on_resize`].
 #[cfg(feature = "tokio")]
 pub fn on_resize() -> Result<tokio::sync::watch::Receiver<TerminalSize>, io::Error> {
-    let terminal_size = size()?;
-    let (tx, rx) = tokio::sync::watch::channel(terminal_size);
+    Terminal::tty()?.on_resize()
+}
 
-    sys::spawn_on_resize_task(tx)?;
+/// Returns a receiver that receives the new size when the terminal is resized, on runtimes
+/// built on `async-io`. See [`Terminal::on_resize_async`].
+#[cfg(all(unix, feature = "async-io"))]
+pub fn on_resize_async() -> Result<AsyncResizeReceiver, io::Error> {
+    Terminal::tty()?.on_resize_async()
+}
+
+/// When new terminal settings take effect.
+///
+/// On Windows the console applies settings immediately, [`ApplyTiming::Flush`] still
+/// discards pending input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyTiming {
+    /// Immediately, like `TCSANOW`.
+    Now,
+    /// After all pending output was written, like `TCSADRAIN`.
+    Drain,
+    /// After all pending output was written, discarding unread input, like `TCSAFLUSH`.
+    Flush,
+}
 
-    Ok(rx)
+/// Options to fine-tune which terminal settings raw mode turns off.
+///
+/// The defaults match [`enable_raw_mode`]. On Windows only [`RawModeOptions::signals`]
+/// has an effect, the other settings do not exist for the console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawModeOptions {
+    signals: bool,
+    output_processing: bool,
+    eight_bit_input: bool,
+    flow_control: bool,
+    timing: ApplyTiming,
+}
+
+impl RawModeOptions {
+    /// Creates options that produce the same mode as [`enable_raw_mode`].
+    pub fn new() -> Self {
+        Self {
+            signals: false,
+            output_processing: false,
+            eight_bit_input: true,
+            flow_control: false,
+            timing: ApplyTiming::Now,
+        }
+    }
+
+    /// Keeps signal generation, so `Ctrl-C`, `Ctrl-\` and `Ctrl-Z` still send their signals.
+    pub fn signals(mut self, signals: bool) -> Self {
+        self.signals = signals;
+        self
+    }
+
+    /// Keeps output post-processing, so `\n` is still written as `\r\n`.
+    pub fn output_processing(mut self, output_processing: bool) -> Self {
+        self.output_processing = output_processing;
+        self
+    }
+
+    /// Forces 8-bit clean input without parity or stripping of the eighth bit.
+    /// When disabled, the character size and parity settings are left untouched.
+    pub fn eight_bit_input(mut self, eight_bit_input: bool) -> Self {
+        self.eight_bit_input = eight_bit_input;
+        self
+    }
+
+    /// Keeps software flow control, so `Ctrl-S` and `Ctrl-Q` still pause and resume output.
+    pub fn flow_control(mut self, flow_control: bool) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
+    /// Sets when raw mode takes effect. Defaults to [`ApplyTiming::Now`].
+    ///
+    /// [`ApplyTiming::Flush`] discards keys that were typed ahead before raw mode was enabled.
+    pub fn timing(mut self, timing: ApplyTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Enables raw mode with these options.
+    /// Once the returned guard is dropped, the previous mode is restored.
+    pub fn enable(&self) -> Result<RawModeGuard, io::Error> {
+        Terminal::tty()?.enable_raw_mode_with(self)
+    }
+}
+
+impl Default for RawModeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// A guard that restores the previous terminal mode when dropped.
+///
+/// Dropping the guard ignores errors, use [`RawModeGuard::restore`] to handle them.
+/// On Unix, the previous mode is applied once all pending output is written, so nothing
+/// that was written in raw mode gets processed with the previous settings.
 pub struct RawModeGuard {
-    original_state: sys::TerminalState,
+    guard: ModeGuard,
 }
 
 impl RawModeGuard {
-    fn new() -> Result<Self, io::Error> {
-        let original_state = sys::enable_raw_mode()?;
+    fn new(terminal: Terminal, options: &RawModeOptions) -> Result<Self, io::Error> {
+        let original_state = sys::enable_raw_mode(&terminal.handle, options)?;
+        let guard = ModeGuard::new(terminal, original_state);
+
+        // The saved state is a safety net, raw mode works without it.
+        #[cfg(unix)]
+        let _ = recovery::save(&guard.terminal.handle, guard.outermost_state());
+
+        Ok(Self { guard })
+    }
+}
+
+/// A guard that restores the previous terminal mode when dropped.
+pub struct CbreakModeGuard {
+    guard: ModeGuard,
+}
+
+impl CbreakModeGuard {
+    fn new(terminal: Terminal) -> Result<Self, io::Error> {
+        let original_state = sys::enable_cbreak_mode(&terminal.handle)?;
+
+        Ok(Self {
+            guard: ModeGuard::new(terminal, original_state),
+        })
+    }
+}
+
+/// A guard that restores the previous terminal mode when dropped.
+pub struct NoEchoGuard {
+    guard: ModeGuard,
+}
+
+impl NoEchoGuard {
+    fn new(terminal: Terminal) -> Result<Self, io::Error> {
+        let original_state = sys::enable_no_echo_mode(&terminal.handle)?;
+
+        Ok(Self {
+            guard: ModeGuard::new(terminal, original_state),
+        })
+    }
+}
+
+/// Implements the methods that every mode guard shares on top of its `guard: ModeGuard`.
+macro_rules! impl_mode_guard {
+    ($($guard:ty),*) => {$(
+        impl $guard {
+            /// Restores the previous mode, reporting errors that dropping the guard would ignore.
+            ///
+            /// Fails if a newer guard of the same terminal is still alive, in which case the
+            /// terminal is left untouched and restored once the newer guards are dropped.
+            pub fn restore(mut self) -> Result<(), io::Error> {
+                self.guard.restore(None)
+            }
+
+            /// Restores the previous mode with the given timing instead of the default of
+            /// draining output first.
+            pub fn restore_with(mut self, timing: ApplyTiming) -> Result<(), io::Error> {
+                self.guard.restore(Some(timing))
+            }
+
+            /// Returns the state the terminal is restored to.
+            pub fn original_state(&self) -> TerminalState {
+                self.guard.original_state()
+            }
+        }
+    )*};
+}
+
+impl_mode_guard!(RawModeGuard, CbreakModeGuard, NoEchoGuard);
+
+/// Shared restore logic of the mode guards.
+struct ModeGuard {
+    terminal: Terminal,
+    layer: Option<stack::LayerId>,
+}
+
+impl ModeGuard {
+    fn new(terminal: Terminal, original_state: sys::TerminalState) -> Self {
+        let layer = stack::push(&terminal.handle, original_state);
+
+        Self {
+            terminal,
+            layer: Some(layer),
+        }
+    }
+
+    fn original_state(&self) -> sys::TerminalState {
+        stack::original_state(self.layer())
+    }
+
+    /// Returns the state the terminal had before any guard was created for it.
+    #[cfg(unix)]
+    fn outermost_state(&self) -> sys::TerminalState {
+        stack::outermost_state(self.layer())
+    }
+
+    /// Restores the previous mode, unless a newer guard of the same terminal is still alive.
+    /// Without a `timing`, output is drained first if possible.
+    fn restore(&mut self, timing: Option<ApplyTiming>) -> Result<(), io::Error> {
+        let Some(layer) = self.layer.take() else {
+            return Ok(());
+        };
+
+        match stack::pop(layer) {
+            stack::Pop::Restore(state, registration) => {
+                let result = match timing {
+                    Some(timing) => sys::restore_mode_with(&self.terminal.handle, state, timing),
+                    None => sys::restore_mode(&self.terminal.handle, state),
+                };
+
+                if let Some(registration) = registration {
+                    restore::unregister(registration);
+                }
+                #[cfg(unix)]
+                if result.is_ok() && !stack::contains(&self.terminal.handle) {
+                    let _ = recovery::remove(&self.terminal.handle);
+                }
+                result
+            }
+            stack::Pop::OutOfOrder => Err(stack::out_of_order_error()),
+        }
+    }
 
-        Ok(Self { original_state })
+    fn layer(&self) -> &stack::LayerId {
+        self.layer
+            .as_ref()
+            .expect("the layer is only taken when restoring")
     }
 }
 
-impl Drop for RawModeGuard {
-    /// Restores the previous mode.
+impl Drop for ModeGuard {
     fn drop(&mut self) {
-        let _ = sys::restore_mode(self.original_state);
+        if let Err(err) = self.restore(None) {
+            if stack::is_out_of_order_error(&err) {
+                stack::report_out_of_order(&err);
+            }
+        }
     }
 }
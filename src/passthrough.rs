@@ -0,0 +1,223 @@
+//! Runs a program on a pseudo-terminal that is shown in the terminal of the process, like
+//! `script(1)`.
+//!
+//! The terminal is put into raw mode, so every key reaches the program unchanged, and the
+//! pseudo-terminal follows the size of the terminal. Once the program exits, the terminal
+//! mode is restored and the exit status returned.
+//!
+//! ```no_run
+//! use std::process::Command;
+//!
+//! let status = terminal_utils::passthrough::run(Command::new("sh")).unwrap();
+//! println!("the shell exited with {status}");
+//! ```
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::process::{Command, ExitStatus};
+
+use crate::{sys, PtyCommand, PtyMaster, ResizeForwarding, Terminal};
+
+type Hook = Box<dyn FnMut(&mut Vec<u8>) + Send>;
+
+/// Runs `command` in a pass-through session on the controlling terminal and returns its exit
+/// status. See [`Passthrough`] to observe or transform the bytes.
+pub fn run(command: Command) -> Result<ExitStatus, io::Error> {
+    Passthrough::new(command).run()
+}
+
+/// A pass-through session with hooks for the bytes that are copied in both directions.
+///
+/// Each hook gets the bytes of one read and may change them in place, for example to log the
+/// session or to filter escape sequences. Bytes can be split across calls arbitrarily.
+///
+/// ```no_run
+/// use std::process::Command;
+///
+/// use terminal_utils::passthrough::Passthrough;
+///
+/// let mut log = Vec::new();
+/// let status = Passthrough::new(Command::new("sh"))
+///     .on_output(move |bytes| log.extend_from_slice(bytes))
+///     .on_input(|bytes| bytes.retain(|&byte| byte != 0x07))
+///     .run()
+///     .unwrap();
+/// ```
+pub struct Passthrough {
+    command: Command,
+    on_input: Option<Hook>,
+    on_output: Option<Hook>,
+}
+
+impl fmt::Debug for Passthrough {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Passthrough")
+            .field("command", &self.command)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Passthrough {
+    /// Prepares a session that runs `command`.
+    pub fn new(command: Command) -> Self {
+        Self {
+            command,
+            on_input: None,
+            on_output: None,
+        }
+    }
+
+    /// Calls `hook` with the input read from the terminal before it is sent to the program.
+    pub fn on_input(mut self, hook: impl FnMut(&mut Vec<u8>) + Send + 'static) -> Self {
+        self.on_input = Some(Box::new(hook));
+        self
+    }
+
+    /// Calls `hook` with the output of the program before it is written to the terminal.
+    pub fn on_output(mut self, hook: impl FnMut(&mut Vec<u8>) + Send + 'static) -> Self {
+        self.on_output = Some(Box::new(hook));
+        self
+    }
+
+    /// Runs the session on the controlling terminal and returns the exit status of the program.
+    pub fn run(self) -> Result<ExitStatus, io::Error> {
+        self.run_on(&Terminal::tty()?)
+    }
+
+    /// Runs the session on `terminal` and returns the exit status of the program.
+    ///
+    /// The program starts with the size and the settings of `terminal`. If copying fails, the
+    /// program is killed. If the terminal hangs up, the program keeps running without input.
+    pub fn run_on(mut self, terminal: &Terminal) -> Result<ExitStatus, io::Error> {
+        let mut child = PtyCommand::new(self.command, terminal.size()?)
+            .termios(&terminal.snapshot()?)
+            .spawn()?;
+
+        let copied = terminal.enable_raw_mode().and_then(|raw_mode_guard| {
+            let subscription = ResizeForwarding::new().start(terminal, child.master())?;
+            let copied = copy(
+                terminal,
+                child.master(),
+                &mut self.on_input,
+                &mut self.on_output,
+            );
+            drop(subscription);
+
+            // Fails if the terminal hung up, which does not concern the program.
+            let _ = raw_mode_guard.restore();
+            copied
+        });
+        if let Err(err) = copied {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(err);
+        }
+
+        child.wait()
+    }
+}
+
+/// Copies between the terminal and the master until the program closed the pseudo-terminal.
+fn copy(
+    terminal: &Terminal,
+    master: &PtyMaster,
+    on_input: &mut Option<Hook>,
+    on_output: &mut Option<Hook>,
+) -> Result<(), io::Error> {
+    let mut buffer = [0u8; 4096];
+    // input that the master did not accept yet
+    let mut input = Vec::new();
+    let mut input_open = true;
+
+    loop {
+        let mut fds = [
+            // A negative descriptor is ignored, so a hung up terminal does not report
+            // `POLLHUP` over and over.
+            libc::pollfd {
+                fd: if input_open { terminal.as_raw_fd() } else { -1 },
+                events: if input.is_empty() { libc::POLLIN } else { 0 },
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: master.as_raw_fd(),
+                events: if input.is_empty() {
+                    libc::POLLIN
+                } else {
+                    libc::POLLIN | libc::POLLOUT
+                },
+                revents: 0,
+            },
+        ];
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        if fds[1].revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+            match (&*master).read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(read) => {
+                    let mut output = buffer[..read].to_vec();
+                    if let Some(hook) = on_output {
+                        hook(&mut output);
+                    }
+                    sys::write_all(&terminal.handle, &output)?;
+                }
+                // every descriptor of the slave is closed
+                Err(err) if err.raw_os_error() == Some(libc::EIO) => return Ok(()),
+                Err(err) if is_retryable(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        if fds[1].revents & libc::POLLOUT != 0 {
+            match (&*master).write(&input) {
+                Ok(written) => {
+                    input.drain(..written);
+                }
+                Err(err) if is_retryable(&err) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        if fds[0].revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0 {
+            let read = unsafe {
+                libc::read(
+                    terminal.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+            match read {
+                // the program keeps running without input, like with a closed stdin
+                0 => input_open = false,
+                -1 => {
+                    let err = io::Error::last_os_error();
+                    if err.raw_os_error() == Some(libc::EIO) {
+                        input_open = false;
+                    } else if !is_retryable(&err) {
+                        return Err(err);
+                    }
+                }
+                read => {
+                    let mut bytes = buffer[..read as usize].to_vec();
+                    if let Some(hook) = on_input {
+                        hook(&mut bytes);
+                    }
+                    input.extend_from_slice(&bytes);
+                }
+            }
+        }
+    }
+}
+
+fn is_retryable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+    )
+}
@@ -0,0 +1,392 @@
+//! Creates pseudo-terminals.
+
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+
+#[cfg(feature = "tokio")]
+use crate::AsyncTerminal;
+use crate::{sys, ResizeSubscription, Terminal, TerminalSize, TermiosSnapshot};
+
+/// A pseudo-terminal created by [`openpty`].
+#[derive(Debug)]
+pub struct Pty {
+    /// The side that the hosting process reads output from and writes input to.
+    pub master: PtyMaster,
+    /// The terminal that the hosted program uses, usually as its standard streams and
+    /// controlling terminal.
+    pub slave: Terminal,
+}
+
+/// The master side of a pseudo-terminal.
+///
+/// Reading returns what the program on the slave side writes, writing sends input to it.
+/// The descriptor is nonblocking, so reads and writes that cannot make progress fail with
+/// [`io::ErrorKind::WouldBlock`]. Once every descriptor of the slave is closed, reads fail with
+/// `EIO` on Linux instead of reporting the end of the file.
+#[derive(Debug)]
+pub struct PtyMaster {
+    fd: OwnedFd,
+}
+
+impl PtyMaster {
+    /// Returns the size of the pseudo-terminal.
+    pub fn size(&self) -> Result<TerminalSize, io::Error> {
+        sys::fd_size(self.fd.as_raw_fd())
+    }
+
+    /// Resizes the pseudo-terminal, which sends `SIGWINCH` to its foreground process group.
+    pub fn set_size(&self, size: TerminalSize) -> Result<(), io::Error> {
+        sys::set_fd_size(self.fd.as_raw_fd(), size)
+    }
+
+    /// Turns the master into an [`AsyncTerminal`] for reads and writes under tokio, which must
+    /// be called from within a tokio runtime with IO enabled.
+    #[cfg(feature = "tokio")]
+    pub fn into_async(self) -> Result<AsyncTerminal, io::Error> {
+        crate::async_terminal::from_master(self)
+    }
+
+    /// Creates a new independently owned handle for the same master.
+    pub fn try_clone(&self) -> Result<Self, io::Error> {
+        Ok(Self {
+            fd: self.fd.try_clone()?,
+        })
+    }
+}
+
+impl Read for PtyMaster {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Read for &PtyMaster {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = unsafe { libc::read(self.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(read as usize)
+    }
+}
+
+impl Write for PtyMaster {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Write for &PtyMaster {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = unsafe { libc::write(self.as_raw_fd(), buf.as_ptr().cast(), buf.len()) };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsFd for PtyMaster {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for PtyMaster {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<PtyMaster> for OwnedFd {
+    fn from(master: PtyMaster) -> Self {
+        master.fd
+    }
+}
+
+/// Opens a new pseudo-terminal of the given size.
+///
+/// The slave starts with `termios` if given, typically a [`Terminal::snapshot`] of the terminal
+/// the hosting process runs in, and with the default settings of the system otherwise. Both
+/// descriptors are closed on exec. The master is nonblocking, while the slave stays blocking
+/// because programs expect that of their standard streams.
+///
+/// ```
+/// use terminal_utils::TerminalSize;
+///
+/// let size = TerminalSize {
+///     width: 80,
+///     height: 24,
+///     pixel_width: 0,
+///     pixel_height: 0,
+/// };
+/// let parent = terminal_utils::Terminal::tty().unwrap();
+/// let pty = terminal_utils::openpty(size, Some(&parent.snapshot().unwrap())).unwrap();
+/// let _raw_mode_guard = pty.slave.enable_raw_mode().unwrap();
+/// assert_eq!(pty.slave.size().unwrap(), size);
+/// ```
+pub fn openpty(size: TerminalSize, termios: Option<&TermiosSnapshot>) -> Result<Pty, io::Error> {
+    let termios = termios.map(TermiosSnapshot::to_termios);
+    let (master, slave) = sys::openpty(size, termios.as_ref())?;
+
+    Ok(Pty {
+        master: PtyMaster { fd: master },
+        slave: Terminal::from(slave),
+    })
+}
+
+/// Runs a [`Command`] on a new pseudo-terminal.
+///
+/// The child gets the slave as its standard streams and, as the leader of a new session, as
+/// its controlling terminal, so it behaves as if it ran in a terminal emulator. The settings
+/// of the command for the standard streams are replaced.
+///
+/// ```
+/// use std::process::Command;
+///
+/// use terminal_utils::{PtyCommand, TerminalSize};
+///
+/// let size = TerminalSize {
+///     width: 80,
+///     height: 24,
+///     pixel_width: 0,
+///     pixel_height: 0,
+/// };
+/// let mut child = PtyCommand::new(Command::new("true"), size).spawn().unwrap();
+/// assert!(child.wait().unwrap().success());
+/// ```
+#[derive(Debug)]
+pub struct PtyCommand {
+    command: Command,
+    size: TerminalSize,
+    termios: Option<TermiosSnapshot>,
+}
+
+impl PtyCommand {
+    /// Prepares to run `command` on a pseudo-terminal of the given size.
+    pub fn new(mut command: Command, size: TerminalSize) -> Self {
+        // Only async-signal-safe functions are called between fork and exec.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                // the slave is the standard input by now
+                if libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        Self {
+            command,
+            size,
+            termios: None,
+        }
+    }
+
+    /// Starts the pseudo-terminal with the given settings, typically a [`Terminal::snapshot`]
+    /// of the terminal the parent runs in, instead of the default settings of the system.
+    pub fn termios(&mut self, termios: &TermiosSnapshot) -> &mut Self {
+        self.termios = Some(*termios);
+        self
+    }
+
+    /// Gives access to the command, for example to add arguments after the fact.
+    pub fn command_mut(&mut self) -> &mut Command {
+        &mut self.command
+    }
+
+    /// Opens a pseudo-terminal and runs the command on it.
+    pub fn spawn(&mut self) -> Result<PtyChild, io::Error> {
+        let pty = openpty(self.size, self.termios.as_ref())?;
+        let slave = pty.slave.as_fd();
+
+        self.command
+            .stdin(Stdio::from(slave.try_clone_to_owned()?))
+            .stdout(Stdio::from(slave.try_clone_to_owned()?))
+            .stderr(Stdio::from(slave.try_clone_to_owned()?));
+        let child = self.command.spawn();
+
+        // The command keeps the slave open otherwise, which keeps the master from noticing
+        // that the child closed it.
+        self.command
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit());
+
+        Ok(PtyChild {
+            master: pty.master,
+            child: child?,
+        })
+    }
+}
+
+/// A child process running on a pseudo-terminal, created by [`PtyCommand::spawn`].
+///
+/// Dropping it neither kills nor waits for the child.
+#[derive(Debug)]
+pub struct PtyChild {
+    master: PtyMaster,
+    child: Child,
+}
+
+impl PtyChild {
+    /// Returns the master side of the pseudo-terminal, to read the output of the child and to
+    /// send it input.
+    pub fn master(&self) -> &PtyMaster {
+        &self.master
+    }
+
+    /// Returns the process id of the child.
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Resizes the pseudo-terminal, which sends `SIGWINCH` to the foreground process group of
+    /// the child's session.
+    pub fn resize(&self, size: TerminalSize) -> Result<(), io::Error> {
+        self.master.set_size(size)
+    }
+
+    /// Waits for the child to exit.
+    pub fn wait(&mut self) -> Result<ExitStatus, io::Error> {
+        self.child.wait()
+    }
+
+    /// Returns the exit status if the child has exited, without blocking.
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>, io::Error> {
+        self.child.try_wait()
+    }
+
+    /// Sends `SIGKILL` to the child.
+    pub fn kill(&mut self) -> Result<(), io::Error> {
+        self.child.kill()
+    }
+
+    /// Returns the master and the child process.
+    pub fn into_parts(self) -> (PtyMaster, Child) {
+        (self.master, self.child)
+    }
+}
+
+/// Mirrors the size of a terminal onto a pseudo-terminal, for programs that host another
+/// program in the terminal they run in.
+///
+/// By default the pseudo-terminal gets the same size. When the hosted program only gets a part
+/// of the screen, the size can be scaled and then adjusted by an offset, for example to make
+/// room for a status line. The pixel size follows the number of cells, so the cells keep
+/// their size in pixels.
+///
+/// ```no_run
+/// use std::process::Command;
+///
+/// use terminal_utils::{PtyCommand, ResizeForwarding, Terminal};
+///
+/// let terminal = Terminal::tty().unwrap();
+/// let forwarding = ResizeForwarding::new().offset(0, -1);
+/// let size = forwarding.apply(terminal.size().unwrap());
+/// let mut child = PtyCommand::new(Command::new("vi"), size).spawn().unwrap();
+///
+/// let subscription = forwarding.start(&terminal, child.master()).unwrap();
+/// child.wait().unwrap();
+/// drop(subscription);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResizeForwarding {
+    width_scale: f64,
+    height_scale: f64,
+    width_offset: i32,
+    height_offset: i32,
+}
+
+impl ResizeForwarding {
+    /// Creates a forwarding that copies the size unchanged.
+    pub fn new() -> Self {
+        Self {
+            width_scale: 1.0,
+            height_scale: 1.0,
+            width_offset: 0,
+            height_offset: 0,
+        }
+    }
+
+    /// Multiplies the columns and rows, for example by 0.5 for half of the screen.
+    /// The result is rounded to the nearest cell.
+    pub fn scale(mut self, width: f64, height: f64) -> Self {
+        self.width_scale = width;
+        self.height_scale = height;
+        self
+    }
+
+    /// Adds to the columns and rows after scaling, for example -1 rows for a status line.
+    pub fn offset(mut self, columns: i32, rows: i32) -> Self {
+        self.width_offset = columns;
+        self.height_offset = rows;
+        self
+    }
+
+    /// Returns the size that the pseudo-terminal gets for a terminal of the given size.
+    /// The pseudo-terminal keeps at least one column and row.
+    pub fn apply(&self, size: TerminalSize) -> TerminalSize {
+        let width = scale_cells(size.width, self.width_scale, self.width_offset);
+        let height = scale_cells(size.height, self.height_scale, self.height_offset);
+
+        TerminalSize {
+            width,
+            height,
+            pixel_width: scale_pixels(size.pixel_width, size.width, width),
+            pixel_height: scale_pixels(size.pixel_height, size.height, height),
+        }
+    }
+
+    /// Resizes `pty` to match `terminal` now and whenever `terminal` is resized, until the
+    /// returned subscription is dropped or the pseudo-terminal is closed.
+    pub fn start(
+        &self,
+        terminal: &Terminal,
+        pty: &PtyMaster,
+    ) -> Result<ResizeSubscription, io::Error> {
+        pty.set_size(self.apply(terminal.size()?))?;
+
+        let forwarding = *self;
+        let pty = pty.try_clone()?;
+        let id = crate::resize::subscribe(terminal, move |size| {
+            pty.set_size(forwarding.apply(size)).is_ok()
+        })?;
+
+        Ok(ResizeSubscription { id })
+    }
+}
+
+impl Default for ResizeForwarding {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn scale_cells(cells: u16, scale: f64, offset: i32) -> u16 {
+    let scaled = (f64::from(cells) * scale).round() as i64 + i64::from(offset);
+    scaled.clamp(1, i64::from(u16::MAX)) as u16
+}
+
+fn scale_pixels(pixels: u16, cells: u16, new_cells: u16) -> u16 {
+    if cells == 0 {
+        return 0;
+    }
+
+    (u32::from(pixels) * u32::from(new_cells) / u32::from(cells)).min(u32::from(u16::MAX)) as u16
+}
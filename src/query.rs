@@ -0,0 +1,122 @@
+//! Asks the terminal for its size in pixels with escape sequences, for terminals that do not
+//! report it through the window size.
+//!
+//! The replies arrive as input, so the terminal is put into raw mode while waiting for them.
+//! A primary device attributes request is sent last. Every terminal answers it, so its reply
+//! marks the end of the replies without waiting for the timeout.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::{sys, Terminal, TerminalSize};
+
+/// Requests the text area size in pixels, the cell size in pixels, the text area size in
+/// characters and the primary device attributes.
+const QUERY: &[u8] = b"\x1b[14t\x1b[16t\x1b[18t\x1b[c";
+
+#[derive(Debug, Default)]
+struct Replies {
+    /// Width and height of the text area in pixels.
+    text_area: Option<(u16, u16)>,
+    /// Width and height of a cell in pixels.
+    cell: Option<(u16, u16)>,
+    /// Columns and rows of the text area.
+    characters: Option<(u16, u16)>,
+    /// The device attributes arrived, so no more replies are coming.
+    complete: bool,
+}
+
+pub(crate) fn pixel_size(
+    terminal: &Terminal,
+    timeout: Duration,
+) -> Result<TerminalSize, io::Error> {
+    let mut size = terminal.size()?;
+    if size.pixel_width > 0 && size.pixel_height > 0 {
+        return Ok(size);
+    }
+
+    let guard = terminal.enable_raw_mode()?;
+    sys::write_all(&terminal.handle, QUERY)?;
+    let replies = read_replies(terminal, timeout)?;
+    guard.restore()?;
+
+    if let Some((columns, rows)) = replies.characters {
+        if size.width == 0 || size.height == 0 {
+            size.width = columns;
+            size.height = rows;
+        }
+    }
+
+    match (replies.text_area, replies.cell) {
+        (Some((width, height)), _) if width > 0 && height > 0 => {
+            size.pixel_width = width;
+            size.pixel_height = height;
+        }
+        (_, Some((width, height))) => {
+            size.pixel_width = width.saturating_mul(size.width);
+            size.pixel_height = height.saturating_mul(size.height);
+        }
+        _ => {}
+    }
+
+    Ok(size)
+}
+
+fn read_replies(terminal: &Terminal, timeout: Duration) -> Result<Replies, io::Error> {
+    let deadline = Instant::now() + timeout;
+    let mut input = Vec::new();
+    let mut buffer = [0; 256];
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(parse_replies(&input));
+        }
+
+        match sys::read_timeout(&terminal.handle, &mut buffer, remaining)? {
+            // the terminal hung up, no further replies will arrive
+            Some(0) => return Ok(parse_replies(&input)),
+            Some(read) => input.extend_from_slice(&buffer[..read]),
+            None => {}
+        }
+
+        let replies = parse_replies(&input);
+        if replies.complete {
+            return Ok(replies);
+        }
+    }
+}
+
+/// Picks the replies out of `input`, skipping anything else the user typed in the meantime.
+fn parse_replies(mut input: &[u8]) -> Replies {
+    let mut replies = Replies::default();
+
+    while let Some(start) = input.windows(2).position(|bytes| bytes == b"\x1b[") {
+        input = &input[start + 2..];
+        let Some(end) = input.iter().position(|byte| (0x40..=0x7e).contains(byte)) else {
+            break;
+        };
+        let (parameters, final_byte) = (&input[..end], input[end]);
+        input = &input[end + 1..];
+
+        match final_byte {
+            b'c' if parameters.starts_with(b"?") => replies.complete = true,
+            b't' => {
+                let numbers: Option<Vec<u16>> = parameters
+                    .split(|&byte| byte == b';')
+                    .map(|number| std::str::from_utf8(number).ok()?.parse().ok())
+                    .collect();
+
+                match numbers.as_deref() {
+                    Some(&[4, height, width]) => replies.text_area = Some((width, height)),
+                    Some(&[6, height, width]) => replies.cell = Some((width, height)),
+                    Some(&[8, rows, columns]) => replies.characters = Some((columns, rows)),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    replies
+}
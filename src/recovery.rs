@@ -0,0 +1,170 @@
+//! Saves the original state of a terminal to a file, so it can be recovered after the process
+//! is killed in a way no hook can handle, like `SIGKILL`.
+//!
+//! There is one file per terminal in the runtime directory. It is written when a raw mode guard
+//! is created and removed once the last guard of the terminal is restored.
+
+use std::fmt::Write as _;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::Write as _;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{env, io, mem, ptr};
+
+use crate::{sys, TermiosSnapshot};
+
+static PERSIST_STATE: AtomicBool = AtomicBool::new(false);
+
+/// Saves the original terminal state to a file whenever a raw mode guard is created.
+///
+/// If the process is killed before the guard is restored, for example by `SIGKILL`, the
+/// terminal can be reset to exactly the saved state with [`crate::recover_terminal`] or the
+/// `terminal-utils recover` command, run on the same terminal. The file is removed once the
+/// last guard of the terminal is restored.
+///
+/// The files are kept in `$XDG_RUNTIME_DIR/terminal-utils`, or in a directory of the current
+/// user in the temporary directory if that variable is not set. The directory is created with
+/// mode 0700 and not used if it belongs to another user or others can write to it. Raw mode is
+/// enabled even if the state cannot be saved. Disabled by default.
+///
+/// ```
+/// terminal_utils::set_persist_state(true);
+///
+/// let raw_mode_guard = terminal_utils::enable_raw_mode().unwrap();
+/// // Even `kill -9` can be recovered from with `terminal-utils recover`.
+/// drop(raw_mode_guard);
+/// ```
+pub fn set_persist_state(enabled: bool) {
+    PERSIST_STATE.store(enabled, Ordering::Relaxed);
+}
+
+/// Writes `state` as the state to recover `handle` to, if persisting is enabled.
+pub(crate) fn save(handle: &sys::Handle, state: sys::TerminalState) -> Result<(), io::Error> {
+    if !PERSIST_STATE.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let device = sys::device_path(handle)?;
+    let path = state_path(handle)?;
+    let directory = path.parent().expect("the state file is in a directory");
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(directory)?;
+    check_directory(directory)?;
+
+    let mut contents = format!(
+        "# Terminal state of {}, saved by process {}.\n\
+         # Run `terminal-utils recover` on that terminal to restore it.\n",
+        device.display(),
+        std::process::id(),
+    );
+    for line in TermiosSnapshot::from(state).to_string().lines() {
+        let _ = writeln!(contents, "# {line}");
+    }
+    let bytes = unsafe {
+        std::slice::from_raw_parts(
+            ptr::from_ref(&state.0).cast::<u8>(),
+            mem::size_of::<libc::termios>(),
+        )
+    };
+    for byte in bytes {
+        let _ = write!(contents, "{byte:02x}");
+    }
+    contents.push('\n');
+
+    // written next to the file and renamed, so a crash never leaves half a file behind
+    let temporary = path.with_extension(format!("{}.tmp", std::process::id()));
+    match fs::remove_file(&temporary) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&temporary)?
+        .write_all(contents.as_bytes())?;
+    fs::rename(&temporary, &path)
+}
+
+/// Reads the state saved for `handle`.
+pub(crate) fn load(handle: &sys::Handle) -> Result<sys::TerminalState, io::Error> {
+    let path = state_path(handle)?;
+    check_directory(path.parent().expect("the state file is in a directory"))?;
+    let contents = fs::read_to_string(path)?;
+
+    let hex: String = contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(|line| line.trim().chars())
+        .collect();
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .filter(|bytes| bytes.len() == mem::size_of::<libc::termios>())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid terminal state file"))?;
+
+    let termios = unsafe { ptr::read_unaligned(bytes.as_ptr().cast::<libc::termios>()) };
+    Ok(sys::TerminalState(termios))
+}
+
+/// Removes the state file of `handle`, if persisting is enabled.
+pub(crate) fn remove(handle: &sys::Handle) -> Result<(), io::Error> {
+    if !PERSIST_STATE.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    match discard(handle) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Removes the state file of `handle`, whether persisting is enabled or not.
+pub(crate) fn discard(handle: &sys::Handle) -> Result<(), io::Error> {
+    let path = state_path(handle)?;
+    check_directory(path.parent().expect("the state file is in a directory"))?;
+    fs::remove_file(path)
+}
+
+/// Returns the path of the state file of `handle`, named after its device, for example
+/// `pts-3` for `/dev/pts/3`.
+fn state_path(handle: &sys::Handle) -> Result<PathBuf, io::Error> {
+    let device = sys::device_path(handle)?;
+    let device = device.strip_prefix("/dev").unwrap_or(&device);
+    let name = device.to_string_lossy().trim_matches('/').replace('/', "-");
+
+    let directory = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) if !runtime_dir.is_empty() => {
+            PathBuf::from(runtime_dir).join("terminal-utils")
+        }
+        _ => env::temp_dir().join(format!("terminal-utils-{}", unsafe { libc::getuid() })),
+    };
+
+    Ok(directory.join(name))
+}
+
+/// Fails unless `directory` is a directory of the current user that nobody else can write to.
+/// The fallback directory in the temporary directory has a predictable name, so another user
+/// could have created it first to plant or redirect state files.
+fn check_directory(directory: &Path) -> Result<(), io::Error> {
+    let metadata = fs::symlink_metadata(directory)?;
+    if !metadata.is_dir()
+        || metadata.uid() != unsafe { libc::getuid() }
+        || metadata.mode() & 0o022 != 0
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a private directory of the current user",
+                directory.display()
+            ),
+        ));
+    }
+
+    Ok(())
+}
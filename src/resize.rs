@@ -0,0 +1,471 @@
+//! Notifies about terminal resizes without an async runtime.
+//!
+//! A single thread waits for resizes, signalled by `SIGWINCH` on Unix and checked periodically
+//! on Windows. It then checks the size of every subscribed terminal and calls the subscribers
+//! whose terminal changed size. Subscribers are removed once they are dropped or their
+//! callback reports that nobody is listening anymore.
+
+use std::cell::Cell;
+use std::ops::Deref;
+#[cfg(unix)]
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+#[cfg(feature = "futures")]
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use std::{fmt, io, thread};
+
+#[cfg(feature = "futures")]
+use futures_core::Stream;
+
+use crate::{sys, Terminal, TerminalSize};
+
+/// Called with the new size, returns `false` once the subscriber is gone.
+type Callback = Box<dyn FnMut(TerminalSize) -> bool + Send>;
+
+/// Tells without a resize that the subscriber is gone.
+type IsClosed = Box<dyn Fn() -> bool + Send>;
+
+struct Subscriber {
+    id: u64,
+    terminal: Terminal,
+    size: TerminalSize,
+    callback: Arc<Mutex<Callback>>,
+    is_closed: Option<IsClosed>,
+}
+
+impl Subscriber {
+    fn is_closed(&self) -> bool {
+        self.is_closed.as_ref().is_some_and(|is_closed| is_closed())
+    }
+}
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static LISTENING: Mutex<bool> = Mutex::new(false);
+
+thread_local! {
+    /// Set on the thread that waits for resizes and calls the subscribers.
+    static IN_LISTENER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Stops the notifications of [`Terminal::on_resize_callback`] and the forwarding of
+/// [`crate::ResizeForwarding::start`] when dropped.
+///
+/// Dropping it waits for a call of the callback that is already running and then drops the
+/// callback, so whatever the callback captured is released once the drop returns.
+pub struct ResizeSubscription {
+    pub(crate) id: u64,
+}
+
+impl fmt::Debug for ResizeSubscription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResizeSubscription").finish_non_exhaustive()
+    }
+}
+
+impl Drop for ResizeSubscription {
+    fn drop(&mut self) {
+        unsubscribe(self.id);
+    }
+}
+
+/// A resize reported by [`Terminal::on_resize_debounced`], from the size before the burst of
+/// resizes to the size the terminal settled on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeEvent {
+    pub old: TerminalSize,
+    pub new: TerminalSize,
+}
+
+impl ResizeEvent {
+    /// Tells whether the number of columns changed.
+    pub fn columns_changed(&self) -> bool {
+        self.old.width != self.new.width
+    }
+
+    /// Tells whether the number of rows changed.
+    pub fn rows_changed(&self) -> bool {
+        self.old.height != self.new.height
+    }
+
+    /// Tells whether the size in pixels changed, which can happen without a change of columns
+    /// and rows, for example when the font size changes.
+    pub fn pixels_changed(&self) -> bool {
+        self.old.pixel_width != self.new.pixel_width
+            || self.old.pixel_height != self.new.pixel_height
+    }
+}
+
+/// Receives the notifications of [`Terminal::resize_channel`] and
+/// [`Terminal::on_resize_debounced`].
+///
+/// Dereferences to a [`mpsc::Receiver`] and iterates over the received values like
+/// [`mpsc::Receiver::iter`]. Dropping it stops the notifications.
+#[derive(Debug)]
+pub struct ResizeChannel<T> {
+    rx: mpsc::Receiver<T>,
+    _subscription: ResizeSubscription,
+}
+
+impl<T> Deref for ResizeChannel<T> {
+    type Target = mpsc::Receiver<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.rx
+    }
+}
+
+impl<T> Iterator for ResizeChannel<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.rx.recv().ok()
+    }
+}
+
+/// A stream of the new sizes of a terminal. Created with [`Terminal::resize_stream`].
+///
+/// Sizes that arrive faster than the stream is polled are coalesced, the next item is always
+/// the latest size. The stream never ends, dropping it stops the notifications.
+#[cfg(feature = "futures")]
+#[derive(Debug)]
+pub struct ResizeStream {
+    state: Arc<Mutex<StreamState>>,
+    _subscription: ResizeSubscription,
+}
+
+#[cfg(feature = "futures")]
+#[derive(Debug, Default)]
+struct StreamState {
+    size: Option<TerminalSize>,
+    waker: Option<Waker>,
+}
+
+#[cfg(feature = "futures")]
+impl Stream for ResizeStream {
+    type Item = TerminalSize;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = lock(&self.state);
+        match state.size.take() {
+            Some(size) => Poll::Ready(Some(size)),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// A file descriptor that becomes readable when the terminal is resized, for use with
+/// `poll`, `select` or an event loop. Created with [`Terminal::resize_fd`].
+///
+/// Call [`ResizeFd::take`] once it is readable to clear it and get the new size.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct ResizeFd {
+    read: OwnedFd,
+    size: Arc<Mutex<Option<TerminalSize>>>,
+    _subscription: ResizeSubscription,
+}
+
+#[cfg(unix)]
+impl ResizeFd {
+    /// Returns the latest size if the terminal was resized since the last call, without
+    /// blocking. Afterwards the descriptor is no longer readable until the next resize.
+    pub fn take(&self) -> Option<TerminalSize> {
+        let mut buffer = [0u8; 64];
+        while unsafe { libc::read(self.read.as_raw_fd(), buffer.as_mut_ptr().cast(), 64) } > 0 {}
+
+        lock(&self.size).take()
+    }
+}
+
+#[cfg(unix)]
+impl AsFd for ResizeFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.read.as_fd()
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for ResizeFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read.as_raw_fd()
+    }
+}
+
+/// Receives the new size when the terminal is resized, on runtimes built on `async-io` like
+/// smol and async-std. Created with [`Terminal::on_resize_async`].
+///
+/// Waits on a [`ResizeFd`], so it is fed by the same background listener as the other
+/// notifications. Dropping it stops the notifications.
+#[cfg(all(unix, feature = "async-io"))]
+#[derive(Debug)]
+pub struct AsyncResizeReceiver {
+    fd: async_io::Async<ResizeFd>,
+    size: TerminalSize,
+}
+
+#[cfg(all(unix, feature = "async-io"))]
+impl AsyncResizeReceiver {
+    /// Waits until the terminal is resized and returns the new size. Resizes that happened
+    /// since the last call are coalesced into the latest size.
+    pub async fn changed(&mut self) -> Result<TerminalSize, io::Error> {
+        loop {
+            if let Some(size) = self.fd.get_ref().take() {
+                self.size = size;
+                return Ok(size);
+            }
+            self.fd.readable().await?;
+        }
+    }
+
+    /// Returns the size returned by the last call to [`AsyncResizeReceiver::changed`], or the
+    /// size when the receiver was created.
+    pub fn size(&self) -> TerminalSize {
+        self.size
+    }
+}
+
+/// Calls `callback` with the new size whenever the size of `terminal` changes, until the
+/// returned subscriber is unsubscribed or `callback` returns `false`.
+pub(crate) fn subscribe(
+    terminal: &Terminal,
+    callback: impl FnMut(TerminalSize) -> bool + Send + 'static,
+) -> Result<u64, io::Error> {
+    subscribe_until(terminal, callback, None)
+}
+
+/// Like [`subscribe`], but also removes the subscriber once `is_closed` returns `true`, which
+/// is checked whenever the listener wakes up or another subscriber is added.
+fn subscribe_until(
+    terminal: &Terminal,
+    callback: impl FnMut(TerminalSize) -> bool + Send + 'static,
+    is_closed: Option<IsClosed>,
+) -> Result<u64, io::Error> {
+    listen()?;
+
+    let subscriber = Subscriber {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        terminal: terminal.try_clone()?,
+        size: terminal.size()?,
+        callback: Arc::new(Mutex::new(Box::new(callback))),
+        is_closed,
+    };
+    let id = subscriber.id;
+    let mut subscribers = lock(&SUBSCRIBERS);
+    subscribers.retain(|subscriber| !subscriber.is_closed());
+    subscribers.push(subscriber);
+
+    Ok(id)
+}
+
+pub(crate) fn callback(
+    terminal: &Terminal,
+    mut callback: impl FnMut(TerminalSize) + Send + 'static,
+) -> Result<ResizeSubscription, io::Error> {
+    let id = subscribe(terminal, move |size| {
+        callback(size);
+        true
+    })?;
+
+    Ok(ResizeSubscription { id })
+}
+
+pub(crate) fn channel(terminal: &Terminal) -> Result<ResizeChannel<TerminalSize>, io::Error> {
+    let (tx, rx) = mpsc::channel();
+    let id = subscribe(terminal, move |size| tx.send(size).is_ok())?;
+
+    Ok(ResizeChannel {
+        rx,
+        _subscription: ResizeSubscription { id },
+    })
+}
+
+#[cfg(feature = "futures")]
+pub(crate) fn stream(terminal: &Terminal) -> Result<ResizeStream, io::Error> {
+    let state = Arc::new(Mutex::new(StreamState::default()));
+    let shared = state.clone();
+    let id = subscribe(terminal, move |size| {
+        let mut state = lock(&shared);
+        state.size = Some(size);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        true
+    })?;
+
+    Ok(ResizeStream {
+        state,
+        _subscription: ResizeSubscription { id },
+    })
+}
+
+/// Forwards the sizes to a thread that waits for `delay` without further resizes before
+/// sending an event. The thread ends once the subscription, and with it `size_tx`, is dropped.
+pub(crate) fn debounced(
+    terminal: &Terminal,
+    delay: Duration,
+) -> Result<ResizeChannel<ResizeEvent>, io::Error> {
+    let mut old = terminal.size()?;
+    let (size_tx, size_rx) = mpsc::channel();
+    let (event_tx, event_rx) = mpsc::channel();
+
+    thread::Builder::new()
+        .name("terminal-utils-debounce".into())
+        .spawn(move || {
+            while let Ok(mut new) = size_rx.recv() {
+                loop {
+                    match size_rx.recv_timeout(delay) {
+                        Ok(size) => new = size,
+                        Err(mpsc::RecvTimeoutError::Timeout) => break,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+
+                if new != old {
+                    if event_tx.send(ResizeEvent { old, new }).is_err() {
+                        return;
+                    }
+                    old = new;
+                }
+            }
+        })?;
+    let id = subscribe(terminal, move |size| size_tx.send(size).is_ok())?;
+
+    Ok(ResizeChannel {
+        rx: event_rx,
+        _subscription: ResizeSubscription { id },
+    })
+}
+
+#[cfg(unix)]
+pub(crate) fn fd(terminal: &Terminal) -> Result<ResizeFd, io::Error> {
+    let (read, write) = sys::pipe()?;
+    sys::set_nonblocking(read.as_raw_fd())?;
+    sys::set_nonblocking(write.as_raw_fd())?;
+
+    let size = Arc::new(Mutex::new(None));
+    let latest = size.clone();
+    let id = subscribe(terminal, move |size| {
+        *lock(&latest) = Some(size);
+        // a full pipe is readable already
+        unsafe { libc::write(write.as_raw_fd(), [1u8].as_ptr().cast(), 1) };
+        true
+    })?;
+
+    Ok(ResizeFd {
+        read,
+        size,
+        _subscription: ResizeSubscription { id },
+    })
+}
+
+/// Unsubscribes once every receiver was dropped, which is noticed at the next wakeup of the
+/// listener or the next subscription.
+#[cfg(feature = "tokio")]
+pub(crate) fn watch(
+    terminal: &Terminal,
+) -> Result<tokio::sync::watch::Receiver<TerminalSize>, io::Error> {
+    let (tx, rx) = tokio::sync::watch::channel(terminal.size()?);
+    let tx = Arc::new(tx);
+    let closed_tx = tx.clone();
+    subscribe_until(
+        terminal,
+        move |size| {
+            tx.send_replace(size);
+            !tx.is_closed()
+        },
+        Some(Box::new(move || closed_tx.is_closed())),
+    )?;
+
+    Ok(rx)
+}
+
+#[cfg(all(unix, feature = "async-io"))]
+pub(crate) fn async_io(terminal: &Terminal) -> Result<AsyncResizeReceiver, io::Error> {
+    let size = terminal.size()?;
+    let fd = async_io::Async::new(fd(terminal)?)?;
+
+    Ok(AsyncResizeReceiver { fd, size })
+}
+
+/// Starts the thread that waits for resizes, unless it is running already.
+fn listen() -> Result<(), io::Error> {
+    let mut listening = lock(&LISTENING);
+    if *listening {
+        return Ok(());
+    }
+
+    let signal = sys::ResizeSignal::new()?;
+    thread::Builder::new()
+        .name("terminal-utils-resize".into())
+        .spawn(move || {
+            IN_LISTENER.with(|in_listener| in_listener.set(true));
+            while signal.wait().is_ok() {
+                dispatch();
+            }
+        })?;
+    *listening = true;
+
+    Ok(())
+}
+
+fn dispatch() {
+    let mut subscribers = lock(&SUBSCRIBERS);
+    subscribers.retain(|subscriber| !subscriber.is_closed());
+    let resized: Vec<_> = subscribers
+        .iter_mut()
+        .filter_map(|subscriber| {
+            let size = subscriber.terminal.size().ok()?;
+            if size == subscriber.size {
+                return None;
+            }
+            subscriber.size = size;
+
+            Some((subscriber.id, size, subscriber.callback.clone()))
+        })
+        .collect();
+    // The callbacks run without the lock, so they can subscribe and unsubscribe.
+    drop(subscribers);
+
+    for (id, size, callback) in resized {
+        if !lock(&callback)(size) {
+            lock(&SUBSCRIBERS).retain(|subscriber| subscriber.id != id);
+        }
+    }
+}
+
+fn unsubscribe(id: u64) {
+    let mut subscribers = lock(&SUBSCRIBERS);
+    let Some(index) = subscribers
+        .iter()
+        .position(|subscriber| subscriber.id == id)
+    else {
+        return;
+    };
+    let callback = subscribers.remove(index).callback;
+    drop(subscribers);
+
+    // `dispatch` may hold a clone of the callback, so it is replaced rather than only dropped.
+    // A callback that drops its own subscription keeps running, it cannot be waited for.
+    let replace = |mut callback: std::sync::MutexGuard<'_, Callback>| {
+        *callback = Box::new(|_| false);
+    };
+    if IN_LISTENER.with(Cell::get) {
+        if let Ok(callback) = callback.try_lock() {
+            replace(callback);
+        }
+    } else {
+        replace(lock(&callback));
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,152 @@
+//! Restores the terminal when the process dies without dropping its mode guards.
+//!
+//! Every mode guard registers the state it restores in a fixed-size table. The table is
+//! lock-free, so it can be read from signal handlers.
+
+use std::cell::UnsafeCell;
+use std::io;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Once;
+
+use crate::sys;
+
+const SLOT_COUNT: usize = 32;
+
+const FREE: u8 = 0;
+const WRITING: u8 = 1;
+const READY: u8 = 2;
+
+struct Slot {
+    status: AtomicU8,
+    sequence: AtomicU64,
+    entry: UnsafeCell<MaybeUninit<(sys::RawHandle, sys::TerminalState)>>,
+}
+
+// `entry` is only written while `status` is `WRITING` and only read while it is `READY`.
+unsafe impl Sync for Slot {}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SLOT: Slot = Slot {
+    status: AtomicU8::new(FREE),
+    sequence: AtomicU64::new(0),
+    entry: UnsafeCell::new(MaybeUninit::uninit()),
+};
+
+static SLOTS: [Slot; SLOT_COUNT] = [EMPTY_SLOT; SLOT_COUNT];
+static NEXT_SEQUENCE: AtomicU64 = AtomicU64::new(1);
+
+/// A registered terminal state, removed again with [`unregister`].
+#[derive(Debug)]
+pub(crate) struct Registration(usize);
+
+/// Installs hooks that restore the terminal if the process dies while a mode guard is alive.
+///
+/// Guards are normally restored when they are dropped, which does not happen on `abort`,
+/// [`std::process::exit`] or when the process is killed by a signal. Once the hooks are
+/// installed, every live guard is restored, outermost state last, before:
+///
+/// - the panic message is printed, if the panic ends the process: it happens on the main
+///   thread or panics abort,
+/// - the process exits through [`std::process::exit`],
+/// - the process is killed by `SIGTERM`, `SIGHUP`, `SIGINT`, `SIGQUIT` or `SIGABRT` (Unix only).
+///
+/// Panics on other threads leave the terminal alone, since the guards are still in use while
+/// the process keeps running. A panic on the main thread that is caught with
+/// [`std::panic::catch_unwind`] still restores the terminal, because the hook cannot tell it
+/// apart from one that ends the process.
+///
+/// Signals that already have a handler or are ignored are left alone, so custom handlers
+/// should be installed before calling this function. Calling it more than once has no effect.
+///
+/// ```
+/// terminal_utils::install_restore_hooks().unwrap();
+///
+/// let raw_mode_guard = terminal_utils::enable_raw_mode().unwrap();
+/// // A panic from here on prints its message in the previous mode.
+/// drop(raw_mode_guard);
+/// ```
+pub fn install_restore_hooks() -> Result<(), io::Error> {
+    static INSTALL: Once = Once::new();
+
+    let mut result = Ok(());
+    INSTALL.call_once(|| {
+        let previous_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // Other panics unwind into code that may still use the live guards.
+            if cfg!(panic = "abort") || sys::is_main_thread() {
+                restore_all();
+            }
+            previous_hook(info);
+        }));
+
+        result = sys::install_restore_handlers();
+    });
+
+    result
+}
+
+/// Registers the state `handle` has to be restored to.
+/// Returns `None` if too many guards are alive at the same time.
+pub(crate) fn register(handle: sys::RawHandle, state: sys::TerminalState) -> Option<Registration> {
+    let sequence = NEXT_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+
+    SLOTS.iter().enumerate().find_map(|(index, slot)| {
+        slot.status
+            .compare_exchange(FREE, WRITING, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+
+        unsafe { (*slot.entry.get()).write((handle, state)) };
+        slot.sequence.store(sequence, Ordering::Relaxed);
+        slot.status.store(READY, Ordering::Release);
+
+        Some(Registration(index))
+    })
+}
+
+/// Replaces the state of a registration, keeping its place in the restore order.
+pub(crate) fn update(registration: &Registration, state: sys::TerminalState) {
+    let slot = &SLOTS[registration.0];
+
+    let (handle, _) = unsafe { (*slot.entry.get()).assume_init() };
+    slot.status.store(WRITING, Ordering::Release);
+    unsafe { (*slot.entry.get()).write((handle, state)) };
+    slot.status.store(READY, Ordering::Release);
+}
+
+pub(crate) fn unregister(registration: Registration) {
+    SLOTS[registration.0].status.store(FREE, Ordering::Release);
+}
+
+/// Restores every registered state, the most recently registered one first, so the
+/// outermost state of each terminal is the one that remains.
+///
+/// Only async-signal-safe operations are used.
+pub(crate) fn restore_all() {
+    let mut upper_bound = u64::MAX;
+
+    loop {
+        let mut next = None;
+        for slot in &SLOTS {
+            if slot.status.load(Ordering::Acquire) != READY {
+                continue;
+            }
+
+            let sequence = slot.sequence.load(Ordering::Relaxed);
+            if sequence < upper_bound
+                && next.is_none_or(|(next_sequence, _)| sequence > next_sequence)
+            {
+                next = Some((sequence, slot));
+            }
+        }
+
+        let Some((sequence, slot)) = next else {
+            break;
+        };
+
+        let (handle, state) = unsafe { (*slot.entry.get()).assume_init() };
+        let _ = sys::restore_raw(handle, state);
+
+        upper_bound = sequence;
+    }
+}
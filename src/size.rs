@@ -0,0 +1,95 @@
+use std::env;
+
+use crate::{sys, Terminal, TerminalSize};
+
+/// Where [`size_with_fallback`] found the terminal size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeSource {
+    /// The controlling terminal of the process.
+    Tty,
+    /// Standard output, if it is a terminal.
+    Stdout,
+    /// Standard error, if it is a terminal.
+    Stderr,
+    /// Standard input, if it is a terminal.
+    Stdin,
+    /// The `COLUMNS` and `LINES` environment variables.
+    Environment,
+    /// The default passed to [`size_with_fallback`].
+    Default,
+}
+
+/// A standard stream of the process.
+pub(crate) enum StdStream {
+    Stdout,
+    Stderr,
+    Stdin,
+}
+
+/// Returns the size of the terminal, falling back to other sources where there is no
+/// controlling terminal, like in cron jobs, containers and CI.
+///
+/// The sources are tried in this order, a size of zero counts as unknown:
+///
+/// 1. the controlling terminal, like [`size`](crate::size),
+/// 2. standard output, standard error and standard input,
+/// 3. the `COLUMNS` and `LINES` environment variables, taking the missing one from `default`,
+/// 4. `default`.
+///
+/// ```
+/// use terminal_utils::{SizeSource, TerminalSize};
+///
+/// let default = TerminalSize {
+///     width: 80,
+///     height: 24,
+///     pixel_width: 0,
+///     pixel_height: 0,
+/// };
+/// let (size, source) = terminal_utils::size_with_fallback(default);
+/// if source == SizeSource::Default {
+///     println!("no terminal, assuming {}x{}", size.width, size.height);
+/// }
+/// ```
+pub fn size_with_fallback(default: TerminalSize) -> (TerminalSize, SizeSource) {
+    if let Some(size) = known(Terminal::tty().and_then(|terminal| terminal.size())) {
+        return (size, SizeSource::Tty);
+    }
+
+    let streams = [
+        (StdStream::Stdout, SizeSource::Stdout),
+        (StdStream::Stderr, SizeSource::Stderr),
+        (StdStream::Stdin, SizeSource::Stdin),
+    ];
+    for (stream, source) in streams {
+        if let Some(size) = known(sys::std_stream_size(stream)) {
+            return (size, source);
+        }
+    }
+
+    let columns = env_size("COLUMNS");
+    let lines = env_size("LINES");
+    if columns.is_some() || lines.is_some() {
+        let size = TerminalSize {
+            width: columns.unwrap_or(default.width),
+            height: lines.unwrap_or(default.height),
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        return (size, SizeSource::Environment);
+    }
+
+    (default, SizeSource::Default)
+}
+
+fn known(size: Result<TerminalSize, std::io::Error>) -> Option<TerminalSize> {
+    size.ok().filter(|size| size.width > 0 && size.height > 0)
+}
+
+fn env_size(name: &str) -> Option<u16> {
+    env::var(name)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&size| size > 0)
+}
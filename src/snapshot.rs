@@ -0,0 +1,645 @@
+use std::str::FromStr;
+use std::{fmt, io, mem};
+
+use crate::sys::TerminalState;
+
+type Flag = libc::tcflag_t;
+
+/// The value of a disabled control character.
+const DISABLED: libc::cc_t = libc::_POSIX_VDISABLE as libc::cc_t;
+
+#[derive(Clone, Copy)]
+enum Field {
+    Control,
+    Input,
+    Output,
+    Local,
+}
+
+enum Setting {
+    /// A single bit, rendered as `name` when set and `-name` when not.
+    Flag(&'static str, Flag),
+    /// A group of bits with a name for every value, for example the character size.
+    Choice(Flag, &'static [(&'static str, Flag)]),
+}
+
+/// The flags in the order `stty -a` prints them, one line per field.
+const FLAG_LINES: [(Field, &[Setting]); 4] = [
+    (
+        Field::Control,
+        &[
+            Setting::Flag("parenb", libc::PARENB),
+            Setting::Flag("parodd", libc::PARODD),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("cmspar", libc::CMSPAR),
+            Setting::Choice(
+                libc::CSIZE,
+                &[
+                    ("cs5", libc::CS5),
+                    ("cs6", libc::CS6),
+                    ("cs7", libc::CS7),
+                    ("cs8", libc::CS8),
+                ],
+            ),
+            Setting::Flag("hupcl", libc::HUPCL),
+            Setting::Flag("cstopb", libc::CSTOPB),
+            Setting::Flag("cread", libc::CREAD),
+            Setting::Flag("clocal", libc::CLOCAL),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("crtscts", libc::CRTSCTS),
+        ],
+    ),
+    (
+        Field::Input,
+        &[
+            Setting::Flag("ignbrk", libc::IGNBRK),
+            Setting::Flag("brkint", libc::BRKINT),
+            Setting::Flag("ignpar", libc::IGNPAR),
+            Setting::Flag("parmrk", libc::PARMRK),
+            Setting::Flag("inpck", libc::INPCK),
+            Setting::Flag("istrip", libc::ISTRIP),
+            Setting::Flag("inlcr", libc::INLCR),
+            Setting::Flag("igncr", libc::IGNCR),
+            Setting::Flag("icrnl", libc::ICRNL),
+            Setting::Flag("ixon", libc::IXON),
+            Setting::Flag("ixoff", libc::IXOFF),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("iuclc", libc::IUCLC),
+            Setting::Flag("ixany", libc::IXANY),
+            Setting::Flag("imaxbel", libc::IMAXBEL),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("iutf8", libc::IUTF8),
+        ],
+    ),
+    (
+        Field::Output,
+        &[
+            Setting::Flag("opost", libc::OPOST),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("olcuc", libc::OLCUC),
+            Setting::Flag("ocrnl", libc::OCRNL),
+            Setting::Flag("onlcr", libc::ONLCR),
+            Setting::Flag("onocr", libc::ONOCR),
+            Setting::Flag("onlret", libc::ONLRET),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("ofill", libc::OFILL),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("ofdel", libc::OFDEL),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Choice(libc::NLDLY, &[("nl0", libc::NL0), ("nl1", libc::NL1)]),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Choice(
+                libc::CRDLY,
+                &[
+                    ("cr0", libc::CR0),
+                    ("cr1", libc::CR1),
+                    ("cr2", libc::CR2),
+                    ("cr3", libc::CR3),
+                ],
+            ),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Choice(
+                libc::TABDLY,
+                &[
+                    ("tab0", libc::TAB0),
+                    ("tab1", libc::TAB1),
+                    ("tab2", libc::TAB2),
+                    ("tab3", libc::TAB3),
+                ],
+            ),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Choice(libc::BSDLY, &[("bs0", libc::BS0), ("bs1", libc::BS1)]),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Choice(libc::VTDLY, &[("vt0", libc::VT0), ("vt1", libc::VT1)]),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Choice(libc::FFDLY, &[("ff0", libc::FF0), ("ff1", libc::FF1)]),
+        ],
+    ),
+    (
+        Field::Local,
+        &[
+            Setting::Flag("isig", libc::ISIG),
+            Setting::Flag("icanon", libc::ICANON),
+            Setting::Flag("iexten", libc::IEXTEN),
+            Setting::Flag("echo", libc::ECHO),
+            Setting::Flag("echoe", libc::ECHOE),
+            Setting::Flag("echok", libc::ECHOK),
+            Setting::Flag("echonl", libc::ECHONL),
+            Setting::Flag("noflsh", libc::NOFLSH),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("xcase", libc::XCASE),
+            Setting::Flag("tostop", libc::TOSTOP),
+            Setting::Flag("echoprt", libc::ECHOPRT),
+            Setting::Flag("echoctl", libc::ECHOCTL),
+            Setting::Flag("echoke", libc::ECHOKE),
+            Setting::Flag("flusho", libc::FLUSHO),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Setting::Flag("extproc", libc::EXTPROC),
+        ],
+    ),
+];
+
+/// The control characters in the order `stty -a` prints them.
+const CONTROL_CHARS: &[(&str, usize)] = &[
+    ("intr", libc::VINTR),
+    ("quit", libc::VQUIT),
+    ("erase", libc::VERASE),
+    ("kill", libc::VKILL),
+    ("eof", libc::VEOF),
+    ("eol", libc::VEOL),
+    ("eol2", libc::VEOL2),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    ("swtch", libc::VSWTC),
+    ("start", libc::VSTART),
+    ("stop", libc::VSTOP),
+    ("susp", libc::VSUSP),
+    ("rprnt", libc::VREPRINT),
+    ("werase", libc::VWERASE),
+    ("lnext", libc::VLNEXT),
+    ("discard", libc::VDISCARD),
+];
+
+/// The numeric settings of non-canonical mode, printed after the control characters.
+const TIMEOUTS: &[(&str, usize)] = &[("min", libc::VMIN), ("time", libc::VTIME)];
+
+const SPEEDS: &[(libc::speed_t, u32)] = &[
+    (libc::B0, 0),
+    (libc::B50, 50),
+    (libc::B75, 75),
+    (libc::B110, 110),
+    (libc::B134, 134),
+    (libc::B150, 150),
+    (libc::B200, 200),
+    (libc::B300, 300),
+    (libc::B600, 600),
+    (libc::B1200, 1200),
+    (libc::B1800, 1800),
+    (libc::B2400, 2400),
+    (libc::B4800, 4800),
+    (libc::B9600, 9600),
+    (libc::B19200, 19200),
+    (libc::B38400, 38400),
+    (libc::B57600, 57600),
+    (libc::B115200, 115200),
+    (libc::B230400, 230400),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B460800, 460800),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B500000, 500000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B576000, 576000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B921600, 921600),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B1000000, 1000000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B1152000, 1152000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B1500000, 1500000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B2000000, 2000000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B2500000, 2500000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B3000000, 3000000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B3500000, 3500000),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (libc::B4000000, 4000000),
+];
+
+/// A human-readable copy of the settings of a Unix terminal.
+///
+/// The snapshot is displayed in the style of `stty -a`, with named flags, control characters
+/// like `^C` and the speed in baud. The text can be parsed back into a snapshot, which also
+/// accepts the output of `stty -a` itself. Parsing only restores the settings listed in the
+/// text, converting a `libc::termios` to a snapshot and back is lossless.
+///
+/// ```
+/// let terminal = terminal_utils::Terminal::tty().unwrap();
+/// let before = terminal.snapshot().unwrap();
+///
+/// let raw_mode_guard = terminal.enable_raw_mode().unwrap();
+/// let after = terminal.snapshot().unwrap();
+/// drop(raw_mode_guard);
+///
+/// println!("{before}");
+/// for change in before.diff(&after) {
+///     println!("raw mode changed {change}");
+/// }
+///
+/// let parsed: terminal_utils::TermiosSnapshot = before.to_string().parse().unwrap();
+/// assert!(before.diff(&parsed).is_empty());
+/// ```
+#[derive(Clone, Copy)]
+pub struct TermiosSnapshot(libc::termios);
+
+/// A setting that differs between two snapshots, see [`TermiosSnapshot::diff`].
+///
+/// Displayed in the style of `stty`, for example `-icanon`, `cs7 -> cs8` or `intr = ^C -> ^X`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotChange {
+    /// The input or output speed changed, named `ispeed` or `ospeed`.
+    Speed {
+        name: &'static str,
+        old: u32,
+        new: u32,
+    },
+    /// A control character or one of the `min` and `time` settings changed.
+    ControlChar {
+        name: &'static str,
+        old: libc::cc_t,
+        new: libc::cc_t,
+    },
+    /// A flag was turned on or off.
+    Flag { name: &'static str, enabled: bool },
+    /// A multi-valued setting like the character size changed, from one named value to another.
+    Setting {
+        old: &'static str,
+        new: &'static str,
+    },
+}
+
+impl TermiosSnapshot {
+    /// Wraps raw `termios` settings, for example from `tcgetattr`.
+    pub fn from_termios(termios: libc::termios) -> Self {
+        Self(termios)
+    }
+
+    /// Returns the raw `termios` settings, for example to pass to `tcsetattr`.
+    pub fn to_termios(&self) -> libc::termios {
+        self.0
+    }
+
+    /// Returns the input speed in baud.
+    pub fn input_speed(&self) -> u32 {
+        speed_to_baud(unsafe { libc::cfgetispeed(&self.0) })
+    }
+
+    /// Returns the output speed in baud.
+    pub fn output_speed(&self) -> u32 {
+        speed_to_baud(unsafe { libc::cfgetospeed(&self.0) })
+    }
+
+    /// Returns every setting that is different in `other`.
+    pub fn diff(&self, other: &TermiosSnapshot) -> Vec<SnapshotChange> {
+        let mut changes = Vec::new();
+
+        let speeds = [
+            ("ispeed", self.input_speed(), other.input_speed()),
+            ("ospeed", self.output_speed(), other.output_speed()),
+        ];
+        for (name, old, new) in speeds {
+            if old != new {
+                changes.push(SnapshotChange::Speed { name, old, new });
+            }
+        }
+
+        for &(name, index) in CONTROL_CHARS.iter().chain(TIMEOUTS) {
+            let (old, new) = (self.0.c_cc[index], other.0.c_cc[index]);
+            if old != new {
+                changes.push(SnapshotChange::ControlChar { name, old, new });
+            }
+        }
+
+        for (field, settings) in &FLAG_LINES {
+            let (old_flags, new_flags) = (self.flags(*field), other.flags(*field));
+
+            for setting in *settings {
+                match *setting {
+                    Setting::Flag(name, bit) => {
+                        if old_flags & bit != new_flags & bit {
+                            changes.push(SnapshotChange::Flag {
+                                name,
+                                enabled: new_flags & bit != 0,
+                            });
+                        }
+                    }
+                    Setting::Choice(mask, values) => {
+                        if old_flags & mask != new_flags & mask {
+                            changes.push(SnapshotChange::Setting {
+                                old: choice_name(values, old_flags & mask),
+                                new: choice_name(values, new_flags & mask),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        changes
+    }
+
+    fn flags(&self, field: Field) -> Flag {
+        match field {
+            Field::Control => self.0.c_cflag,
+            Field::Input => self.0.c_iflag,
+            Field::Output => self.0.c_oflag,
+            Field::Local => self.0.c_lflag,
+        }
+    }
+
+    fn flags_mut(&mut self, field: Field) -> &mut Flag {
+        match field {
+            Field::Control => &mut self.0.c_cflag,
+            Field::Input => &mut self.0.c_iflag,
+            Field::Output => &mut self.0.c_oflag,
+            Field::Local => &mut self.0.c_lflag,
+        }
+    }
+
+    fn set_speed(&mut self, name: &str, baud: u32) -> Result<(), io::Error> {
+        let speed = SPEEDS
+            .iter()
+            .find(|&&(_, candidate)| candidate == baud)
+            .map(|&(speed, _)| speed)
+            .ok_or_else(|| invalid_data(format!("unsupported speed: {baud}")))?;
+
+        if name != "ospeed" {
+            wrap_error(unsafe { libc::cfsetispeed(&mut self.0, speed) })?;
+        }
+        if name != "ispeed" {
+            wrap_error(unsafe { libc::cfsetospeed(&mut self.0, speed) })?;
+        }
+
+        Ok(())
+    }
+
+    fn set_flag(&mut self, token: &str) -> Result<(), io::Error> {
+        let (name, enabled) = match token.strip_prefix('-') {
+            Some(name) => (name, false),
+            None => (token, true),
+        };
+
+        for (field, settings) in &FLAG_LINES {
+            for setting in *settings {
+                match *setting {
+                    Setting::Flag(flag_name, bit) if flag_name == name => {
+                        let flags = self.flags_mut(*field);
+                        if enabled {
+                            *flags |= bit;
+                        } else {
+                            *flags &= !bit;
+                        }
+                        return Ok(());
+                    }
+                    Setting::Choice(mask, values) if enabled => {
+                        if let Some(&(_, value)) =
+                            values.iter().find(|(value_name, _)| *value_name == name)
+                        {
+                            let flags = self.flags_mut(*field);
+                            *flags = *flags & !mask | value;
+                            return Ok(());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Err(invalid_data(format!("unknown setting: {token}")))
+    }
+
+    fn set_value(&mut self, name: &str, value: &str) -> Result<(), io::Error> {
+        if name == "line" {
+            let _line = parse_number(Some(value), name)?;
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            {
+                self.0.c_line = u8::try_from(_line)
+                    .map_err(|_| invalid_data(format!("invalid line discipline: {value}")))?;
+            }
+            return Ok(());
+        }
+
+        if let Some(&(_, index)) = TIMEOUTS.iter().find(|(timeout, _)| *timeout == name) {
+            let number = parse_number(Some(value), name)?;
+            self.0.c_cc[index] = libc::cc_t::try_from(number)
+                .map_err(|_| invalid_data(format!("invalid value of {name}: {value}")))?;
+            return Ok(());
+        }
+
+        let &(_, index) = CONTROL_CHARS
+            .iter()
+            .find(|(control_char, _)| *control_char == name)
+            .ok_or_else(|| invalid_data(format!("unknown control character: {name}")))?;
+        self.0.c_cc[index] = parse_control_char(value)
+            .ok_or_else(|| invalid_data(format!("invalid value of {name}: {value}")))?;
+
+        Ok(())
+    }
+}
+
+impl From<TerminalState> for TermiosSnapshot {
+    fn from(state: TerminalState) -> Self {
+        Self(state.0)
+    }
+}
+
+impl From<TermiosSnapshot> for TerminalState {
+    fn from(snapshot: TermiosSnapshot) -> Self {
+        TerminalState(snapshot.0)
+    }
+}
+
+impl fmt::Display for TermiosSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (input_speed, output_speed) = (self.input_speed(), self.output_speed());
+        if input_speed == output_speed {
+            write!(f, "speed {output_speed} baud;")?;
+        } else {
+            write!(f, "ispeed {input_speed} baud; ospeed {output_speed} baud;")?;
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        write!(f, " line = {};", self.0.c_line)?;
+        writeln!(f)?;
+
+        for (index, &(name, char_index)) in CONTROL_CHARS.iter().enumerate() {
+            let separator = if index == 0 { "" } else { " " };
+            write!(
+                f,
+                "{separator}{name} = {};",
+                ControlChar(self.0.c_cc[char_index])
+            )?;
+        }
+        for &(name, index) in TIMEOUTS {
+            write!(f, " {name} = {};", self.0.c_cc[index])?;
+        }
+        writeln!(f)?;
+
+        for (field, settings) in &FLAG_LINES {
+            let flags = self.flags(*field);
+
+            for (index, setting) in settings.iter().enumerate() {
+                let separator = if index == 0 { "" } else { " " };
+                match *setting {
+                    Setting::Flag(name, bit) if flags & bit != 0 => write!(f, "{separator}{name}")?,
+                    Setting::Flag(name, _) => write!(f, "{separator}-{name}")?,
+                    Setting::Choice(mask, values) => {
+                        write!(f, "{separator}{}", choice_name(values, flags & mask))?
+                    }
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TermiosSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = self.to_string();
+        let text = text.trim_end().replace('\n', " ");
+
+        f.debug_tuple("TermiosSnapshot")
+            .field(&format_args!("{text}"))
+            .finish()
+    }
+}
+
+impl FromStr for TermiosSnapshot {
+    type Err = io::Error;
+
+    /// Parses the output of [`TermiosSnapshot`]'s `Display` implementation or of `stty -a`.
+    /// Control characters that are not listed are disabled, flags that are not listed are off.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut snapshot = Self(unsafe { mem::zeroed() });
+        for &(_, index) in CONTROL_CHARS {
+            snapshot.0.c_cc[index] = DISABLED;
+        }
+
+        let mut tokens = text
+            .split(|c: char| c.is_whitespace() || c == ';')
+            .filter(|token| !token.is_empty())
+            .peekable();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "speed" | "ispeed" | "ospeed" => {
+                    let baud = parse_number(tokens.next(), token)?;
+                    if tokens.next() != Some("baud") {
+                        return Err(invalid_data(format!("expected baud after {token}")));
+                    }
+                    snapshot.set_speed(token, baud)?;
+                }
+                // window size, printed by `stty -a` but not part of the settings
+                "rows" | "columns" => {
+                    parse_number(tokens.next(), token)?;
+                }
+                name if tokens.peek() == Some(&"=") => {
+                    tokens.next();
+                    let value = tokens
+                        .next()
+                        .ok_or_else(|| invalid_data(format!("missing value of {name}")))?;
+                    snapshot.set_value(name, value)?;
+                }
+                flag => snapshot.set_flag(flag)?,
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+impl fmt::Display for SnapshotChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Speed { name, old, new } => write!(f, "{name} {old} -> {new} baud"),
+            Self::ControlChar { name, old, new }
+                if TIMEOUTS.iter().any(|(timeout, _)| timeout == name) =>
+            {
+                write!(f, "{name} = {old} -> {new}")
+            }
+            Self::ControlChar { name, old, new } => {
+                write!(f, "{name} = {} -> {}", ControlChar(*old), ControlChar(*new))
+            }
+            Self::Flag {
+                name,
+                enabled: true,
+            } => write!(f, "{name}"),
+            Self::Flag {
+                name,
+                enabled: false,
+            } => write!(f, "-{name}"),
+            Self::Setting { old, new } => write!(f, "{old} -> {new}"),
+        }
+    }
+}
+
+/// Renders a control character the way `stty` does.
+struct ControlChar(libc::cc_t);
+
+impl fmt::Display for ControlChar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut value = self.0;
+        if value == DISABLED {
+            return f.write_str("<undef>");
+        }
+
+        if value >= 0x80 {
+            f.write_str("M-")?;
+            value &= 0x7f;
+        }
+        match value {
+            0x7f => f.write_str("^?"),
+            0..=0x1f => write!(f, "^{}", char::from(value + 0x40)),
+            _ => write!(f, "{}", char::from(value)),
+        }
+    }
+}
+
+fn parse_control_char(text: &str) -> Option<libc::cc_t> {
+    if text == "<undef>" || text == "undef" {
+        return Some(DISABLED);
+    }
+
+    let (meta, text) = match text.strip_prefix("M-") {
+        Some(text) => (0x80, text),
+        None => (0, text),
+    };
+    let value = match text.as_bytes() {
+        [b'^', b'?'] => 0x7f,
+        [b'^', c @ 0x40..=0x5f] => c - 0x40,
+        [b'^', c @ b'a'..=b'z'] => c - b'a' + 1,
+        [c] if c.is_ascii() => *c,
+        _ => return None,
+    };
+
+    Some(meta | value)
+}
+
+fn choice_name(values: &[(&'static str, Flag)], value: Flag) -> &'static str {
+    values
+        .iter()
+        .find(|&&(_, candidate)| candidate == value)
+        .map_or("?", |&(name, _)| name)
+}
+
+/// Speeds that are not in the table are returned as is, which is the baud rate on platforms
+/// like macOS where `speed_t` is the speed itself.
+// `speed_t` is wider than `u32` on some platforms
+#[allow(clippy::unnecessary_cast)]
+fn speed_to_baud(speed: libc::speed_t) -> u32 {
+    SPEEDS
+        .iter()
+        .find(|&&(candidate, _)| candidate == speed)
+        .map_or(speed as u32, |&(_, baud)| baud)
+}
+
+fn parse_number(token: Option<&str>, name: &str) -> Result<u32, io::Error> {
+    token
+        .and_then(|token| token.parse().ok())
+        .ok_or_else(|| invalid_data(format!("expected a number after {name}")))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn wrap_error(result: libc::c_int) -> Result<(), io::Error> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! Keeps track of the mode guards of every terminal, so nested guards restore in the right order.
+//!
+//! Each guard pushes a layer with the state it found. Dropping the newest layer of a terminal
+//! restores that state. Dropping an older layer leaves the terminal alone and hands the state
+//! over to the layer above it, so the terminal only returns to its original state once the
+//! last guard is gone.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::{fmt, io};
+
+use crate::{restore, sys};
+
+static LAYERS: Mutex<Vec<Layer>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static OUT_OF_ORDER_HOOK: Mutex<Option<fn(&io::Error)>> = Mutex::new(None);
+
+struct Layer {
+    id: u64,
+    device: u64,
+    original_state: sys::TerminalState,
+    registration: Option<restore::Registration>,
+}
+
+/// A layer pushed by [`push`], removed again with [`pop`].
+#[derive(Debug)]
+pub(crate) struct LayerId(u64);
+
+pub(crate) enum Pop {
+    /// The layer was the newest of its terminal, which has to be restored to this state.
+    /// The registration has to be removed once the state is restored.
+    Restore(sys::TerminalState, Option<restore::Registration>),
+    /// A newer layer of the same terminal is still alive and took over the state.
+    OutOfOrder,
+}
+
+/// Sets a function that is called when a mode guard is dropped while a newer guard of the
+/// same terminal is still alive.
+///
+/// The terminal is left untouched in that case and returns to its original mode once the
+/// newer guards are dropped. By default, nothing is reported.
+///
+/// ```
+/// terminal_utils::set_out_of_order_hook(|error| {
+///     eprintln!("terminal-utils: {error}");
+/// });
+/// ```
+pub fn set_out_of_order_hook(hook: fn(&io::Error)) {
+    *OUT_OF_ORDER_HOOK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(hook);
+}
+
+pub(crate) fn push(handle: &sys::Handle, original_state: sys::TerminalState) -> LayerId {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+    lock_layers().push(Layer {
+        id,
+        device: handle.device_id(),
+        original_state,
+        registration: restore::register(handle.raw(), original_state),
+    });
+
+    LayerId(id)
+}
+
+pub(crate) fn pop(layer: LayerId) -> Pop {
+    let mut layers = lock_layers();
+
+    let index = find(&layers, &layer);
+    let removed = layers.remove(index);
+
+    let newer = layers[index..]
+        .iter_mut()
+        .find(|newer| newer.device == removed.device);
+
+    match newer {
+        None => Pop::Restore(removed.original_state, removed.registration),
+        Some(newer) => {
+            newer.original_state = removed.original_state;
+            if let Some(registration) = &newer.registration {
+                restore::update(registration, removed.original_state);
+            }
+            if let Some(registration) = removed.registration {
+                restore::unregister(registration);
+            }
+
+            Pop::OutOfOrder
+        }
+    }
+}
+
+/// Returns the state the terminal is restored to once `layer` is popped.
+pub(crate) fn original_state(layer: &LayerId) -> sys::TerminalState {
+    let layers = lock_layers();
+    layers[find(&layers, layer)].original_state
+}
+
+/// Returns the state the terminal of `layer` had before any of its guards was created.
+#[cfg(unix)]
+pub(crate) fn outermost_state(layer: &LayerId) -> sys::TerminalState {
+    let layers = lock_layers();
+    let device = layers[find(&layers, layer)].device;
+
+    layers
+        .iter()
+        .find(|outermost| outermost.device == device)
+        .map(|outermost| outermost.original_state)
+        .expect("the layer itself is on the stack")
+}
+
+/// Tells whether any guard of the terminal of `handle` is alive.
+#[cfg(unix)]
+pub(crate) fn contains(handle: &sys::Handle) -> bool {
+    let device = handle.device_id();
+    lock_layers().iter().any(|layer| layer.device == device)
+}
+
+pub(crate) fn out_of_order_error() -> io::Error {
+    io::Error::other(OutOfOrder)
+}
+
+pub(crate) fn is_out_of_order_error(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .is_some_and(|error| error.is::<OutOfOrder>())
+}
+
+pub(crate) fn report_out_of_order(error: &io::Error) {
+    let hook = *OUT_OF_ORDER_HOOK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(hook) = hook {
+        hook(error);
+    }
+}
+
+#[derive(Debug)]
+struct OutOfOrder;
+
+impl fmt::Display for OutOfOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(
+            "mode guard restored while a newer guard of the same terminal is alive, \
+             the terminal is restored once the newer guards are dropped",
+        )
+    }
+}
+
+impl std::error::Error for OutOfOrder {}
+
+fn find(layers: &[Layer], layer: &LayerId) -> usize {
+    layers
+        .iter()
+        .position(|candidate| candidate.id == layer.0)
+        .expect("layers are only removed by `pop`")
+}
+
+fn lock_layers() -> std::sync::MutexGuard<'static, Vec<Layer>> {
+    LAYERS.lock().unwrap_or_else(PoisonError::into_inner)
+}
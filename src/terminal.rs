@@ -0,0 +1,348 @@
+use std::io;
+#[cfg(unix)]
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+#[cfg(unix)]
+use std::path::Path;
+use std::time::Duration;
+
+#[cfg(all(unix, feature = "async-io"))]
+use crate::AsyncResizeReceiver;
+#[cfg(all(unix, feature = "tokio"))]
+use crate::AsyncTerminal;
+#[cfg(unix)]
+use crate::ResizeFd;
+#[cfg(feature = "futures")]
+use crate::ResizeStream;
+use crate::{
+    sys, CbreakModeGuard, NoEchoGuard, RawModeGuard, RawModeOptions, ResizeChannel, ResizeEvent,
+    ResizeSubscription, TerminalMode, TerminalSize,
+};
+
+/// A handle to a terminal.
+///
+/// [`Terminal::tty`] opens the controlling terminal of the process, which is what the free
+/// functions of this crate use. On Unix a handle can also be created for any other terminal
+/// device, for example a pseudo-terminal that is owned by the process.
+///
+/// ```
+/// let terminal = terminal_utils::Terminal::tty().unwrap();
+/// let size = terminal.size().unwrap();
+/// println!("The terminal is {}x{} characters.", size.width, size.height);
+/// ```
+#[derive(Debug)]
+pub struct Terminal {
+    pub(crate) handle: sys::Handle,
+}
+
+impl Terminal {
+    /// Returns a handle to the controlling terminal of the process.
+    ///
+    /// On Unix all of these handles share one descriptor, which is opened on first use and
+    /// transparently reopened if it stops working.
+    pub fn tty() -> Result<Self, io::Error> {
+        Ok(Self {
+            handle: sys::Handle::tty()?,
+        })
+    }
+
+    /// Opens the terminal device at `path`, for example `/dev/pts/7`.
+    ///
+    /// The device does not become the controlling terminal of the process.
+    #[cfg(unix)]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, io::Error> {
+        Ok(Self {
+            handle: sys::Handle::open(path.as_ref())?,
+        })
+    }
+
+    /// Creates a handle for the terminal behind `fd`.
+    ///
+    /// The file descriptor is duplicated, so `fd` can be closed independently of the handle.
+    #[cfg(unix)]
+    pub fn from_fd(fd: impl AsFd) -> Result<Self, io::Error> {
+        Ok(Self::from(fd.as_fd().try_clone_to_owned()?))
+    }
+
+    /// Creates a new independently owned handle for the same terminal.
+    pub fn try_clone(&self) -> Result<Self, io::Error> {
+        Ok(Self {
+            handle: self.handle.try_clone()?,
+        })
+    }
+
+    /// Returns the size of the terminal.
+    pub fn size(&self) -> Result<TerminalSize, io::Error> {
+        sys::size(&self.handle)
+    }
+
+    /// Sets the size of the terminal, including the pixel size.
+    ///
+    /// This is meant for pseudo-terminals that host child processes: the kernel sends
+    /// `SIGWINCH` to the foreground process group of the terminal when the size changes.
+    ///
+    /// ```no_run
+    /// use terminal_utils::{Terminal, TerminalSize};
+    ///
+    /// let pty = Terminal::open("/dev/pts/7").unwrap();
+    /// pty.set_size(TerminalSize {
+    ///     width: 120,
+    ///     height: 40,
+    ///     pixel_width: 1200,
+    ///     pixel_height: 800,
+    /// })
+    /// .unwrap();
+    /// ```
+    #[cfg(unix)]
+    pub fn set_size(&self, size: TerminalSize) -> Result<(), io::Error> {
+        sys::set_size(&self.handle, size)
+    }
+
+    /// Returns the size of the terminal, asking the terminal for the pixel size if the operating
+    /// system does not know it, which is common and always the case on Windows.
+    ///
+    /// The terminal is put into raw mode while the `CSI 14 t`, `CSI 16 t` and `CSI 18 t`
+    /// requests are answered. Terminals that do not answer within `timeout` leave the pixel
+    /// size at 0. Input typed while waiting for the replies is discarded.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// let terminal = terminal_utils::Terminal::tty().unwrap();
+    /// let size = terminal.query_pixel_size(Duration::from_millis(100)).unwrap();
+    /// if let (Some(width), Some(height)) = (size.cell_width_px(), size.cell_height_px()) {
+    ///     println!("Cells are {width}x{height} pixels.");
+    /// }
+    /// ```
+    pub fn query_pixel_size(&self, timeout: Duration) -> Result<TerminalSize, io::Error> {
+        crate::query::pixel_size(self, timeout)
+    }
+
+    /// Returns the mode the terminal is currently in.
+    pub fn current_mode(&self) -> Result<TerminalMode, io::Error> {
+        sys::current_mode(&self.handle)
+    }
+
+    /// Opens the terminal again for nonblocking reads and writes under tokio, which must be
+    /// called from within a tokio runtime with IO enabled.
+    ///
+    /// The new descriptor is independent of this handle, so the terminal stays blocking for
+    /// everybody else.
+    ///
+    /// ```no_run
+    /// # async fn run() {
+    /// use tokio::io::AsyncReadExt;
+    ///
+    /// let terminal = terminal_utils::Terminal::tty().unwrap();
+    /// let _raw_mode_guard = terminal.enable_raw_mode().unwrap();
+    /// let mut input = terminal.to_async().unwrap();
+    /// let key = input.read_u8().await.unwrap();
+    /// # }
+    /// ```
+    #[cfg(all(unix, feature = "tokio"))]
+    pub fn to_async(&self) -> Result<AsyncTerminal, io::Error> {
+        crate::async_terminal::from_terminal(self)
+    }
+
+    /// Returns a copy of the current settings of the terminal.
+    #[cfg(unix)]
+    pub fn snapshot(&self) -> Result<crate::TermiosSnapshot, io::Error> {
+        sys::current_state(&self.handle).map(Into::into)
+    }
+
+    /// Restores the state saved for this terminal by a process that died in raw mode and
+    /// removes the saved state. Fails with [`io::ErrorKind::NotFound`] if nothing was saved.
+    /// See [`crate::set_persist_state`].
+    #[cfg(unix)]
+    pub fn recover(&self) -> Result<(), io::Error> {
+        let state = crate::recovery::load(&self.handle)?;
+        sys::restore_mode(&self.handle, state)?;
+        crate::recovery::discard(&self.handle)
+    }
+
+    /// Tells whether the raw mode is currently enabled.
+    pub fn is_raw_mode_enabled(&self) -> Result<bool, io::Error> {
+        Ok(self.current_mode()? == TerminalMode::Raw)
+    }
+
+    /// Enables raw mode.
+    /// Once the returned guard is dropped, the previous mode is restored.
+    pub fn enable_raw_mode(&self) -> Result<RawModeGuard, io::Error> {
+        self.enable_raw_mode_with(&RawModeOptions::new())
+    }
+
+    /// Enables raw mode with the given options.
+    /// Once the returned guard is dropped, the previous mode is restored.
+    pub fn enable_raw_mode_with(
+        &self,
+        options: &RawModeOptions,
+    ) -> Result<RawModeGuard, io::Error> {
+        RawModeGuard::new(self.try_clone()?, options)
+    }
+
+    /// Enables cbreak mode, which turns off line buffering and echo but keeps signals
+    /// and output processing.
+    /// Once the returned guard is dropped, the previous mode is restored.
+    pub fn enable_cbreak_mode(&self) -> Result<CbreakModeGuard, io::Error> {
+        CbreakModeGuard::new(self.try_clone()?)
+    }
+
+    /// Enables no-echo mode, which keeps line buffering but stops echoing typed characters.
+    /// Once the returned guard is dropped, the previous mode is restored.
+    pub fn enable_no_echo_mode(&self) -> Result<NoEchoGuard, io::Error> {
+        NoEchoGuard::new(self.try_clone()?)
+    }
+
+    /// Discards input that was received but not read yet.
+    pub fn flush_input(&self) -> Result<(), io::Error> {
+        sys::flush_input(&self.handle)
+    }
+
+    /// Waits until all output written to the terminal was transmitted.
+    pub fn drain_output(&self) -> Result<(), io::Error> {
+        sys::drain_output(&self.handle)
+    }
+
+    /// Calls `callback` with the new size whenever the terminal is resized, until the returned
+    /// subscription is dropped. Works without an async runtime.
+    ///
+    /// The callbacks of all terminals run on one background thread, so they should return
+    /// quickly.
+    ///
+    /// ```
+    /// let terminal = terminal_utils::Terminal::tty().unwrap();
+    /// let subscription = terminal
+    ///     .on_resize_callback(|size| println!("resized to {}x{}", size.width, size.height))
+    ///     .unwrap();
+    /// drop(subscription);
+    /// ```
+    pub fn on_resize_callback(
+        &self,
+        callback: impl FnMut(TerminalSize) + Send + 'static,
+    ) -> Result<ResizeSubscription, io::Error> {
+        crate::resize::callback(self, callback)
+    }
+
+    /// Returns a channel that receives the new size whenever the terminal is resized.
+    /// Works without an async runtime, [`std::sync::mpsc::Receiver::recv`] blocks until the
+    /// next resize. Dropping the channel stops the notifications.
+    ///
+    /// ```no_run
+    /// let terminal = terminal_utils::Terminal::tty().unwrap();
+    /// for size in terminal.resize_channel().unwrap() {
+    ///     println!("resized to {}x{}", size.width, size.height);
+    /// }
+    /// ```
+    pub fn resize_channel(&self) -> Result<ResizeChannel<TerminalSize>, io::Error> {
+        crate::resize::channel(self)
+    }
+
+    /// Returns a channel that receives an event once the terminal size settles after a burst
+    /// of resizes, like the dozens that dragging a window edge causes.
+    ///
+    /// An event is sent when no further resize happened for `delay`. Its old size is the size
+    /// of the previous event, so resizes that end at the size they started from are dropped.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// let terminal = terminal_utils::Terminal::tty().unwrap();
+    /// for event in terminal.on_resize_debounced(Duration::from_millis(100)).unwrap() {
+    ///     if event.columns_changed() || event.rows_changed() {
+    ///         println!("redrawing at {}x{}", event.new.width, event.new.height);
+    ///     }
+    /// }
+    /// ```
+    pub fn on_resize_debounced(
+        &self,
+        delay: Duration,
+    ) -> Result<ResizeChannel<ResizeEvent>, io::Error> {
+        crate::resize::debounced(self, delay)
+    }
+
+    /// Returns a stream of the new sizes of the terminal, to combine resizes with other event
+    /// sources through `futures` combinators or `select!`. Works with any async runtime.
+    ///
+    /// ```no_run
+    /// # async fn run() {
+    /// use futures::StreamExt;
+    ///
+    /// let terminal = terminal_utils::Terminal::tty().unwrap();
+    /// let mut resizes = terminal.resize_stream().unwrap();
+    /// while let Some(size) = resizes.next().await {
+    ///     println!("resized to {}x{}", size.width, size.height);
+    /// }
+    /// # }
+    /// ```
+    #[cfg(feature = "futures")]
+    pub fn resize_stream(&self) -> Result<ResizeStream, io::Error> {
+        crate::resize::stream(self)
+    }
+
+    /// Returns a file descriptor that becomes readable whenever the terminal is resized,
+    /// to wait for resizes together with other descriptors in `poll` or an event loop.
+    #[cfg(unix)]
+    pub fn resize_fd(&self) -> Result<ResizeFd, io::Error> {
+        crate::resize::fd(self)
+    }
+
+    /// Returns a receiver that receives the new size when the terminal is resized.
+    ///
+    /// Every receiver is fed by the same background listener as
+    /// [`Terminal::on_resize_callback`], no task is spawned. The notifications stop once the
+    /// returned receiver and all of its clones are dropped, the subscription is released with
+    /// the next `SIGWINCH` or the next subscription.
+    #[cfg(feature = "tokio")]
+    pub fn on_resize(&self) -> Result<tokio::sync::watch::Receiver<TerminalSize>, io::Error> {
+        crate::resize::watch(self)
+    }
+
+    /// Returns a receiver that receives the new size when the terminal is resized, for smol,
+    /// async-std and other runtimes built on `async-io`.
+    ///
+    /// ```no_run
+    /// # async fn run() {
+    /// let terminal = terminal_utils::Terminal::tty().unwrap();
+    /// let mut resize_rx = terminal.on_resize_async().unwrap();
+    /// loop {
+    ///     let size = resize_rx.changed().await.unwrap();
+    ///     println!("resized to {}x{}", size.width, size.height);
+    /// }
+    /// # }
+    /// ```
+    #[cfg(all(unix, feature = "async-io"))]
+    pub fn on_resize_async(&self) -> Result<AsyncResizeReceiver, io::Error> {
+        crate::resize::async_io(self)
+    }
+}
+
+#[cfg(unix)]
+impl From<OwnedFd> for Terminal {
+    fn from(fd: OwnedFd) -> Self {
+        Self {
+            handle: sys::Handle::from(fd),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl FromRawFd for Terminal {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
+        Self::from(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+/// For the controlling terminal, this is the shared descriptor at the time of the call. It
+/// stays open after the descriptor was reopened, but keeps referring to the hung up terminal.
+#[cfg(unix)]
+impl AsFd for Terminal {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.handle.as_fd()
+    }
+}
+
+#[cfg(unix)]
+impl AsRawFd for Terminal {
+    fn as_raw_fd(&self) -> RawFd {
+        self.handle.as_fd().as_raw_fd()
+    }
+}
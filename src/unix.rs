@@ -1,92 +1,764 @@
+use std::cell::UnsafeCell;
+use std::ffi::{CStr, OsStr};
 use std::fmt::Debug;
-use std::fs::File;
-use std::os::fd::{AsRawFd, RawFd};
+use std::fs::OpenOptions;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
 use std::{io, mem};
 
-use crate::TerminalSize;
+use crate::size::StdStream;
+use crate::{ApplyTiming, RawModeOptions, TerminalMode, TerminalSize, TermiosSnapshot};
 
+pub type RawHandle = RawFd;
+
+/// Signals that kill the process by default and are handled by [`install_restore_handlers`].
+const RESTORE_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGTERM,
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGABRT,
+];
+
+/// The terminal and settings [`suspend_on_signal`] restores before the process stops and
+/// reapplies once it continues. `SUSPEND_FD` is -1 while no handler is installed.
+static SUSPEND_FD: AtomicI32 = AtomicI32::new(-1);
+static SUSPEND_STATE: SuspendState = SuspendState(UnsafeCell::new(MaybeUninit::uninit()));
+static SUSPEND_ACTIVE: AtomicBool = AtomicBool::new(false);
+static SUSPEND_PREVIOUS_ACTION: Mutex<Option<libc::sigaction>> = Mutex::new(None);
+
+struct SuspendState(UnsafeCell<MaybeUninit<(libc::termios, libc::termios)>>);
+
+// Only written before `SUSPEND_FD` is published and only read after it was.
+unsafe impl Sync for SuspendState {}
+
+/// The write end of the pipe [`resume_on_signal`] writes to, -1 while no handler is installed.
+#[cfg(feature = "tokio")]
+static RESUME_PIPE: AtomicI32 = AtomicI32::new(-1);
+#[cfg(feature = "tokio")]
+static RESUME_PREVIOUS_ACTION: Mutex<Option<libc::sigaction>> = Mutex::new(None);
+
+/// The write end of the pipe [`resize_on_signal`] writes to, -1 until [`ResizeSignal::new`].
+static RESIZE_PIPE: AtomicI32 = AtomicI32::new(-1);
+/// The `SIGWINCH` action from before [`ResizeSignal::new`], called by [`resize_on_signal`].
+static RESIZE_PREVIOUS_ACTION: OnceLock<libc::sigaction> = OnceLock::new();
+
+/// The controlling terminal, opened on first use and shared by every [`Handle::Tty`].
+///
+/// A replaced descriptor is never closed, as [`AsFd`] may have handed it out already.
+static TTY: Mutex<Option<BorrowedFd<'static>>> = Mutex::new(None);
+
+#[derive(Debug)]
+pub enum Handle {
+    /// The controlling terminal, through the shared descriptor in [`TTY`].
+    Tty,
+    Fd(OwnedFd),
+}
+
+impl Handle {
+    pub fn tty() -> Result<Self, io::Error> {
+        shared_tty(None)?;
+        Ok(Self::Tty)
+    }
+
+    pub fn open(path: &Path) -> Result<Self, io::Error> {
+        Ok(Self::Fd(open_terminal(path)?))
+    }
+
+    pub fn try_clone(&self) -> Result<Self, io::Error> {
+        match self {
+            Self::Tty => Ok(Self::Tty),
+            Self::Fd(fd) => Ok(Self::Fd(fd.try_clone()?)),
+        }
+    }
+
+    /// Opens the terminal again, so the new descriptor has its own file status flags.
+    #[cfg(feature = "tokio")]
+    pub fn reopen(&self) -> Result<OwnedFd, io::Error> {
+        match self {
+            Self::Tty => open_terminal(Path::new("/dev/tty")),
+            Self::Fd(_) => open_terminal(&device_path(self)?),
+        }
+    }
+
+    pub fn raw(&self) -> RawHandle {
+        self.as_fd().as_raw_fd()
+    }
+
+    /// Identifies the terminal device, so different handles of the same terminal match.
+    pub fn device_id(&self) -> u64 {
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        match self.with_fd(|fd| wrap_error(unsafe { libc::fstat(fd, &mut stat) })) {
+            Ok(()) => stat.st_rdev as u64,
+            Err(_) => self.raw() as u64,
+        }
+    }
+
+    /// Runs `f` with the file descriptor of the terminal.
+    ///
+    /// The shared controlling terminal is reopened and `f` retried once if the descriptor
+    /// turns out to be unusable, for example after a hangup.
+    fn with_fd<T>(&self, mut f: impl FnMut(RawFd) -> Result<T, io::Error>) -> Result<T, io::Error> {
+        match self {
+            Self::Tty => {
+                let fd = shared_tty(None)?;
+                match f(fd.as_raw_fd()) {
+                    Err(err) if is_stale_fd_error(&err) => f(shared_tty(Some(fd))?.as_raw_fd()),
+                    result => result,
+                }
+            }
+            Self::Fd(fd) => f(fd.as_raw_fd()),
+        }
+    }
+}
+
+impl From<OwnedFd> for Handle {
+    fn from(fd: OwnedFd) -> Self {
+        Self::Fd(fd)
+    }
+}
+
+impl AsFd for Handle {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            // Opened by `Handle::tty` and never closed.
+            Self::Tty => (*lock_tty()).expect("the controlling terminal is open"),
+            Self::Fd(fd) => fd.as_fd(),
+        }
+    }
+}
+
+/// The settings of a terminal, as saved by the mode guards.
 #[derive(Clone, Copy)]
-pub struct TerminalState(libc::termios);
+pub struct TerminalState(pub(crate) libc::termios);
 
 impl Debug for TerminalState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_tuple("TerminalState")
-            .field(&self.0.c_iflag)
-            .field(&self.0.c_oflag)
-            .field(&self.0.c_cflag)
-            .field(&self.0.c_lflag)
-            .field(&self.0.c_cc)
-            .field(&self.0.c_ispeed)
-            .field(&self.0.c_ospeed)
+            .field(&TermiosSnapshot::from(*self))
             .finish()
     }
 }
 
-pub fn size() -> Result<TerminalSize, io::Error> {
-    let tty = get_tty()?;
-    let fd = tty.as_raw_fd();
+pub fn size(handle: &Handle) -> Result<TerminalSize, io::Error> {
+    handle.with_fd(fd_size)
+}
 
-    let info = get_winsize(fd)?;
+pub fn set_size(handle: &Handle, size: TerminalSize) -> Result<(), io::Error> {
+    handle.with_fd(|fd| set_fd_size(fd, size))
+}
 
-    Ok(TerminalSize {
-        width: info.ws_col,
-        height: info.ws_row,
+/// Returns the size of the terminal behind `fd`, which need not be a [`Handle`].
+pub fn fd_size(fd: RawFd) -> Result<TerminalSize, io::Error> {
+    get_winsize(fd).map(winsize_to_size)
+}
 
-        pixel_width: info.ws_xpixel,
-        pixel_height: info.ws_ypixel,
+/// Resizes the terminal behind `fd`, which need not be a [`Handle`].
+pub fn set_fd_size(fd: RawFd, size: TerminalSize) -> Result<(), io::Error> {
+    let info = size_to_winsize(size);
+    wrap_error(unsafe { libc::ioctl(fd, libc::TIOCSWINSZ, &info) })
+}
+
+pub fn std_stream_size(stream: StdStream) -> Result<TerminalSize, io::Error> {
+    let fd = match stream {
+        StdStream::Stdout => libc::STDOUT_FILENO,
+        StdStream::Stderr => libc::STDERR_FILENO,
+        StdStream::Stdin => libc::STDIN_FILENO,
+    };
+
+    fd_size(fd)
+}
+
+pub fn current_mode(handle: &Handle) -> Result<TerminalMode, io::Error> {
+    let termios = handle.with_fd(get_terminal_attr)?;
+    let mode = if termios.c_lflag & libc::ICANON == 0 {
+        // Raw mode can keep signals, but always stops translating carriage returns.
+        if termios.c_lflag & libc::ISIG == 0 || termios.c_iflag & libc::ICRNL == 0 {
+            TerminalMode::Raw
+        } else {
+            TerminalMode::Cbreak
+        }
+    } else if termios.c_lflag & libc::ECHO == 0 {
+        TerminalMode::NoEcho
+    } else {
+        TerminalMode::Normal
+    };
+
+    Ok(mode)
+}
+
+pub fn enable_raw_mode(
+    handle: &Handle,
+    options: &RawModeOptions,
+) -> Result<TerminalState, io::Error> {
+    update_terminal_attr(handle, options.timing, |termios| make_raw(termios, options))
+}
+
+pub fn enable_cbreak_mode(handle: &Handle) -> Result<TerminalState, io::Error> {
+    update_terminal_attr(handle, ApplyTiming::Now, |termios| {
+        termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+        termios.c_cc[libc::VMIN] = 1;
+        termios.c_cc[libc::VTIME] = 0;
+    })
+}
+
+pub fn enable_no_echo_mode(handle: &Handle) -> Result<TerminalState, io::Error> {
+    update_terminal_attr(handle, ApplyTiming::Now, |termios| {
+        termios.c_lflag &= !(libc::ECHO | libc::ECHOE | libc::ECHOK | libc::ECHONL);
+    })
+}
+
+pub fn current_state(handle: &Handle) -> Result<TerminalState, io::Error> {
+    Ok(TerminalState(handle.with_fd(get_terminal_attr)?))
+}
+
+/// Returns the path of the terminal device, for example `/dev/pts/3`.
+pub fn device_path(handle: &Handle) -> Result<PathBuf, io::Error> {
+    handle.with_fd(|fd| {
+        let mut buffer = [0 as libc::c_char; 256];
+        match unsafe { libc::ttyname_r(fd, buffer.as_mut_ptr(), buffer.len()) } {
+            0 => {
+                let name = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+                Ok(PathBuf::from(OsStr::from_bytes(name.to_bytes())))
+            }
+            errno => Err(io::Error::from_raw_os_error(errno)),
+        }
+    })
+}
+
+pub fn write_all(handle: &Handle, mut bytes: &[u8]) -> Result<(), io::Error> {
+    handle.with_fd(|fd| {
+        while !bytes.is_empty() {
+            let written = unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+            match written {
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                -1 => return Err(io::Error::last_os_error()),
+                written => bytes = &bytes[written as usize..],
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Reads whatever input arrives within `timeout`. Returns `None` if nothing did or a signal
+/// interrupted the wait, and `Some(0)` once the terminal hung up.
+pub fn read_timeout(
+    handle: &Handle,
+    buffer: &mut [u8],
+    timeout: Duration,
+) -> Result<Option<usize>, io::Error> {
+    handle.with_fd(|fd| {
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+            0 => return Ok(None),
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    return Ok(None);
+                }
+                return Err(err);
+            }
+            _ => {}
+        }
+        if pollfd.revents & libc::POLLIN == 0 {
+            // `POLLHUP` or `POLLERR`, no more input will arrive
+            return Ok(Some(0));
+        }
+
+        let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if read == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(read as usize))
+    })
+}
+
+pub fn restore_mode(handle: &Handle, original_termios: TerminalState) -> Result<(), io::Error> {
+    handle.with_fd(|fd| {
+        set_terminal_attr(fd, libc::TCSADRAIN, &original_termios.0)
+            .or_else(|_| set_terminal_attr(fd, libc::TCSANOW, &original_termios.0))
     })
 }
 
-pub fn is_raw_mode_enabled() -> Result<bool, io::Error> {
-    let tty = get_tty()?;
-    let fd = tty.as_raw_fd();
+pub fn restore_mode_with(
+    handle: &Handle,
+    original_termios: TerminalState,
+    timing: ApplyTiming,
+) -> Result<(), io::Error> {
+    handle.with_fd(|fd| set_terminal_attr(fd, optional_actions(timing), &original_termios.0))
+}
+
+pub fn flush_input(handle: &Handle) -> Result<(), io::Error> {
+    handle.with_fd(|fd| wrap_error(unsafe { libc::tcflush(fd, libc::TCIFLUSH) }))
+}
+
+pub fn drain_output(handle: &Handle) -> Result<(), io::Error> {
+    handle.with_fd(|fd| wrap_error(unsafe { libc::tcdrain(fd) }))
+}
+
+/// Restores `original_termios` without touching the shared descriptor, so it is safe to
+/// call from a signal handler.
+pub fn restore_raw(fd: RawHandle, original_termios: TerminalState) -> Result<(), io::Error> {
+    set_terminal_attr(fd, libc::TCSANOW, &original_termios.0)
+}
+
+/// Installs the signal and exit handlers of [`crate::install_restore_hooks`].
+pub fn install_restore_handlers() -> Result<(), io::Error> {
+    for signal in RESTORE_SIGNALS {
+        let mut previous: libc::sigaction = unsafe { mem::zeroed() };
+        wrap_error(unsafe { libc::sigaction(signal, ptr::null(), &mut previous) })?;
+        if previous.sa_sigaction != libc::SIG_DFL {
+            continue;
+        }
+
+        set_signal_handler(signal, restore_on_signal, libc::SA_RESETHAND, None)?;
+    }
+
+    if unsafe { libc::atexit(restore_on_exit) } != 0 {
+        return Err(io::Error::other("failed to register exit handler"));
+    }
+
+    Ok(())
+}
+
+extern "C" fn restore_on_signal(signal: libc::c_int) {
+    crate::restore::restore_all();
 
-    let termios = get_terminal_attr(fd)?;
-    Ok((termios.c_lflag & libc::ICANON) == 0)
+    // `SA_RESETHAND` already reset the handler, so the signal is delivered again with its
+    // default action once this handler returns.
+    unsafe { libc::raise(signal) };
 }
 
-pub fn enable_raw_mode() -> Result<TerminalState, io::Error> {
-    let tty = get_tty()?;
-    let fd = tty.as_raw_fd();
+extern "C" fn restore_on_exit() {
+    crate::restore::restore_all();
+}
 
-    let mut termios = get_terminal_attr(fd)?;
-    let original_termios = termios;
+/// Tells whether the current thread is the main thread of the process.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn is_main_thread() -> bool {
+    unsafe { libc::gettid() == libc::getpid() }
+}
 
-    unsafe { libc::cfmakeraw(&mut termios) };
-    set_terminal_attr(fd, &termios)?;
+/// Tells whether the current thread is the main thread of the process.
+#[cfg(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd"
+))]
+pub fn is_main_thread() -> bool {
+    unsafe { libc::pthread_main_np() == 1 }
+}
 
-    Ok(TerminalState(original_termios))
+/// Tells whether the current thread is the main thread of the process, going by the name the
+/// standard library gives it.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd"
+)))]
+pub fn is_main_thread() -> bool {
+    std::thread::current().name() == Some("main")
 }
 
-pub fn restore_mode(original_termios: TerminalState) -> Result<(), io::Error> {
-    let tty = get_tty()?;
-    let fd = tty.as_raw_fd();
+/// Installs a `SIGTSTP` handler that restores `original_termios` on `handle` before the
+/// process is stopped and reapplies `current_termios` once it continues.
+/// Only one handler can be installed at a time.
+pub fn install_suspend_handler(
+    handle: RawHandle,
+    original_termios: TerminalState,
+    current_termios: TerminalState,
+) -> Result<(), io::Error> {
+    if SUSPEND_ACTIVE.swap(true, Ordering::Acquire) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "job control is already enabled",
+        ));
+    }
+
+    unsafe { (*SUSPEND_STATE.0.get()).write((original_termios.0, current_termios.0)) };
+    SUSPEND_FD.store(handle, Ordering::Release);
+
+    let mut previous: libc::sigaction = unsafe { mem::zeroed() };
+    let result = set_signal_handler(libc::SIGTSTP, suspend_on_signal, 0, Some(&mut previous));
+    if let Err(err) = result {
+        SUSPEND_FD.store(-1, Ordering::Release);
+        SUSPEND_ACTIVE.store(false, Ordering::Release);
+        return Err(err);
+    }
 
-    set_terminal_attr(fd, &original_termios.0)?;
+    *SUSPEND_PREVIOUS_ACTION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(previous);
 
     Ok(())
 }
 
+/// Removes the handler of [`install_suspend_handler`] and reinstates the previous one.
+pub fn uninstall_suspend_handler() -> Result<(), io::Error> {
+    let previous = SUSPEND_PREVIOUS_ACTION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+
+    if let Some(previous) = previous {
+        wrap_error(unsafe { libc::sigaction(libc::SIGTSTP, &previous, ptr::null_mut()) })?;
+    }
+
+    SUSPEND_FD.store(-1, Ordering::Release);
+    SUSPEND_ACTIVE.store(false, Ordering::Release);
+
+    Ok(())
+}
+
+/// Stops the process the same way `Ctrl-Z` does.
+pub fn suspend() -> Result<(), io::Error> {
+    wrap_error(unsafe { libc::raise(libc::SIGTSTP) })
+}
+
+extern "C" fn suspend_on_signal(signal: libc::c_int) {
+    let fd = SUSPEND_FD.load(Ordering::Acquire);
+    let states = (fd != -1).then(|| unsafe { (*SUSPEND_STATE.0.get()).assume_init() });
+
+    if let Some((original_termios, _)) = &states {
+        let _ = set_terminal_attr(fd, libc::TCSANOW, original_termios);
+    }
+
+    // Stop with the default action. The signal is blocked while its handler runs, so it
+    // has to be unblocked for the raised signal to be delivered right away.
+    unsafe {
+        let mut default: libc::sigaction = mem::zeroed();
+        default.sa_sigaction = libc::SIG_DFL;
+        libc::sigaction(signal, &default, ptr::null_mut());
+
+        let mut mask: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        libc::sigaddset(&mut mask, signal);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &mask, ptr::null_mut());
+
+        libc::raise(signal);
+    }
+
+    // Execution continues here after `SIGCONT`.
+    let _ = set_signal_handler(signal, suspend_on_signal, 0, None);
+
+    if let Some((_, current_termios)) = &states {
+        let _ = set_terminal_attr(fd, libc::TCSANOW, current_termios);
+    }
+}
+
+fn set_signal_handler(
+    signal: libc::c_int,
+    handler: extern "C" fn(libc::c_int),
+    flags: libc::c_int,
+    previous: Option<&mut libc::sigaction>,
+) -> Result<(), io::Error> {
+    let mut action: libc::sigaction = unsafe { mem::zeroed() };
+    action.sa_sigaction = handler as libc::sighandler_t;
+    action.sa_flags = flags;
+    wrap_error(unsafe { libc::sigemptyset(&mut action.sa_mask) })?;
+
+    let previous = previous.map_or(ptr::null_mut(), |previous| previous as *mut _);
+    wrap_error(unsafe { libc::sigaction(signal, &action, previous) })
+}
+
+/// Wakes up on `SIGWINCH` through a self-pipe, so no signal handling happens in the thread
+/// that waits for it.
+pub struct ResizeSignal(OwnedFd);
+
+impl ResizeSignal {
+    /// Installs the `SIGWINCH` handler. Only called once per process, the handler stays
+    /// installed and calls the previous handler as well.
+    pub fn new() -> Result<Self, io::Error> {
+        let (read, write) = pipe()?;
+        set_nonblocking(write.as_raw_fd())?;
+        RESIZE_PIPE.store(write.into_raw_fd(), Ordering::Release);
+
+        let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+            resize_on_signal;
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        action.sa_sigaction = handler as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        wrap_error(unsafe { libc::sigemptyset(&mut action.sa_mask) })?;
+
+        let mut previous: libc::sigaction = unsafe { mem::zeroed() };
+        wrap_error(unsafe { libc::sigaction(libc::SIGWINCH, &action, &mut previous) })?;
+        let _ = RESIZE_PREVIOUS_ACTION.set(previous);
+
+        Ok(Self(read))
+    }
+
+    /// Blocks until the next `SIGWINCH`. Signals that arrived since the last call count.
+    pub fn wait(&self) -> Result<(), io::Error> {
+        wait_for_wakeup(self.0.as_fd())
+    }
+}
+
+/// Reads the pending wakeups of a self-pipe, blocking until there is at least one.
+fn wait_for_wakeup(fd: BorrowedFd<'_>) -> Result<(), io::Error> {
+    let mut buffer = [0u8; 64];
+    loop {
+        let read = unsafe { libc::read(fd.as_raw_fd(), buffer.as_mut_ptr().cast(), 64) };
+        match read {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            -1 => return Err(io::Error::last_os_error()),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => return Ok(()),
+        }
+    }
+}
+
+extern "C" fn resize_on_signal(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+) {
+    let errno = errno::errno();
+    let fd = RESIZE_PIPE.load(Ordering::Acquire);
+    if fd != -1 {
+        // a full pipe already has a wakeup pending
+        unsafe { libc::write(fd, [1u8].as_ptr().cast(), 1) };
+    }
+    errno::set_errno(errno);
+
+    let Some(previous) = RESIZE_PREVIOUS_ACTION.get() else {
+        return;
+    };
+    match previous.sa_sigaction {
+        libc::SIG_DFL | libc::SIG_IGN => {}
+        handler if previous.sa_flags & libc::SA_SIGINFO != 0 => unsafe {
+            let handler: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) =
+                mem::transmute(handler);
+            handler(signal, info, context);
+        },
+        handler => unsafe {
+            let handler: extern "C" fn(libc::c_int) = mem::transmute(handler);
+            handler(signal);
+        },
+    }
+}
+
+/// Wakes up on `SIGCONT` through a self-pipe, see [`install_resume_handler`].
 #[cfg(feature = "tokio")]
-pub fn spawn_on_resize_task(
-    tx: tokio::sync::watch::Sender<TerminalSize>,
-) -> Result<tokio::task::JoinHandle<()>, io::Error> {
-    let mut signal = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change())?;
+pub struct ResumeSignal(OwnedFd);
 
-    let task = tokio::spawn(async move {
-        loop {
-            signal.recv().await;
+#[cfg(feature = "tokio")]
+impl ResumeSignal {
+    /// Blocks until the process continues. Fails with [`io::ErrorKind::UnexpectedEof`] once
+    /// [`uninstall_resume_handler`] was called.
+    pub fn wait(&self) -> Result<(), io::Error> {
+        wait_for_wakeup(self.0.as_fd())
+    }
+}
 
-            if let Ok(size) = size() {
-                tx.send_replace(size);
-            }
+/// Installs a `SIGCONT` handler that wakes up the returned [`ResumeSignal`].
+/// Only one handler can be installed at a time, which [`install_suspend_handler`] ensures.
+#[cfg(feature = "tokio")]
+pub fn install_resume_handler() -> Result<ResumeSignal, io::Error> {
+    let (read, write) = pipe()?;
+    set_nonblocking(write.as_raw_fd())?;
+    RESUME_PIPE.store(write.into_raw_fd(), Ordering::Release);
+
+    let mut previous: libc::sigaction = unsafe { mem::zeroed() };
+    let result = set_signal_handler(
+        libc::SIGCONT,
+        resume_on_signal,
+        libc::SA_RESTART,
+        Some(&mut previous),
+    );
+    if let Err(err) = result {
+        close_resume_pipe();
+        return Err(err);
+    }
+
+    *RESUME_PREVIOUS_ACTION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(previous);
+
+    Ok(ResumeSignal(read))
+}
+
+/// Removes the handler of [`install_resume_handler`], reinstates the previous one and closes
+/// the pipe, which ends the waiting of its [`ResumeSignal`].
+#[cfg(feature = "tokio")]
+pub fn uninstall_resume_handler() -> Result<(), io::Error> {
+    let previous = RESUME_PREVIOUS_ACTION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+
+    let result = match previous {
+        Some(previous) => {
+            wrap_error(unsafe { libc::sigaction(libc::SIGCONT, &previous, ptr::null_mut()) })
         }
-    });
-    Ok(task)
+        None => Ok(()),
+    };
+    close_resume_pipe();
+
+    result
 }
 
-fn get_tty() -> Result<File, io::Error> {
-    File::open("/dev/tty")
+#[cfg(feature = "tokio")]
+fn close_resume_pipe() {
+    let fd = RESUME_PIPE.swap(-1, Ordering::AcqRel);
+    if fd != -1 {
+        drop(unsafe { OwnedFd::from_raw_fd(fd) });
+    }
+}
+
+#[cfg(feature = "tokio")]
+extern "C" fn resume_on_signal(_: libc::c_int) {
+    let errno = errno::errno();
+    let fd = RESUME_PIPE.load(Ordering::Acquire);
+    if fd != -1 {
+        // a full pipe already has a wakeup pending
+        unsafe { libc::write(fd, [1u8].as_ptr().cast(), 1) };
+    }
+    errno::set_errno(errno);
+}
+
+/// Opens a pseudo-terminal and returns its master and slave, both closed on exec. The master
+/// is nonblocking, the slave stays blocking for the programs that use it as standard streams.
+pub fn openpty(
+    size: TerminalSize,
+    termios: Option<&libc::termios>,
+) -> Result<(OwnedFd, OwnedFd), io::Error> {
+    let mut master = -1;
+    let mut slave = -1;
+    let info = size_to_winsize(size);
+    let termios = termios.map_or(ptr::null(), |termios| termios as *const libc::termios);
+    wrap_error(unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            termios.cast_mut(),
+            &info as *const libc::winsize as *mut libc::winsize,
+        )
+    })?;
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+    for fd in [&master, &slave] {
+        wrap_error(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) })?;
+    }
+    set_nonblocking(master.as_raw_fd())?;
+
+    Ok((master, slave))
+}
+
+/// Creates a pipe whose ends are closed on exec.
+pub fn pipe() -> Result<(OwnedFd, OwnedFd), io::Error> {
+    let mut fds = [-1; 2];
+    wrap_error(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+    for fd in [&read, &write] {
+        wrap_error(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) })?;
+    }
+
+    Ok((read, write))
+}
+
+pub fn set_nonblocking(fd: RawFd) -> Result<(), io::Error> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    wrap_error(flags)?;
+    wrap_error(unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) })
+}
+
+/// Applies `update` to the current settings and returns the settings from before.
+fn update_terminal_attr(
+    handle: &Handle,
+    timing: ApplyTiming,
+    update: impl Fn(&mut libc::termios),
+) -> Result<TerminalState, io::Error> {
+    handle.with_fd(|fd| {
+        let mut termios = get_terminal_attr(fd)?;
+        let original_termios = termios;
+
+        update(&mut termios);
+        set_terminal_attr(fd, optional_actions(timing), &termios)?;
+
+        Ok(TerminalState(original_termios))
+    })
+}
+
+/// Same as `cfmakeraw`, but leaves the settings selected in `options` untouched.
+fn make_raw(termios: &mut libc::termios, options: &RawModeOptions) {
+    termios.c_iflag &= !(libc::IGNBRK | libc::PARMRK | libc::INLCR | libc::IGNCR | libc::ICRNL);
+    termios.c_lflag &= !(libc::ECHO | libc::ECHONL | libc::ICANON | libc::IEXTEN);
+
+    if !options.signals {
+        termios.c_iflag &= !libc::BRKINT;
+        termios.c_lflag &= !libc::ISIG;
+    }
+    if !options.output_processing {
+        termios.c_oflag &= !libc::OPOST;
+    }
+    if !options.flow_control {
+        termios.c_iflag &= !libc::IXON;
+    }
+    if options.eight_bit_input {
+        termios.c_iflag &= !libc::ISTRIP;
+        termios.c_cflag &= !(libc::CSIZE | libc::PARENB);
+        termios.c_cflag |= libc::CS8;
+    }
+
+    termios.c_cc[libc::VMIN] = 1;
+    termios.c_cc[libc::VTIME] = 0;
+}
+
+/// Returns the shared descriptor of the controlling terminal, opening it if needed.
+/// A `stale` descriptor that is still cached gets replaced by a freshly opened one.
+fn shared_tty(stale: Option<BorrowedFd<'static>>) -> Result<BorrowedFd<'static>, io::Error> {
+    let mut tty = lock_tty();
+
+    if let Some(fd) = *tty {
+        if stale.map(|stale| stale.as_raw_fd()) != Some(fd.as_raw_fd()) {
+            return Ok(fd);
+        }
+    }
+
+    let fd = open_terminal(Path::new("/dev/tty"))?.into_raw_fd();
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+    *tty = Some(fd);
+
+    Ok(fd)
+}
+
+fn lock_tty() -> std::sync::MutexGuard<'static, Option<BorrowedFd<'static>>> {
+    TTY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn is_stale_fd_error(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EBADF | libc::EIO | libc::ENXIO)
+    )
+}
+
+fn open_terminal(path: &Path) -> Result<OwnedFd, io::Error> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY)
+        .open(path)?;
+
+    Ok(file.into())
 }
 
 fn get_winsize(fd: RawFd) -> Result<libc::winsize, io::Error> {
@@ -96,6 +768,25 @@ fn get_winsize(fd: RawFd) -> Result<libc::winsize, io::Error> {
     Ok(info)
 }
 
+fn winsize_to_size(info: libc::winsize) -> TerminalSize {
+    TerminalSize {
+        width: info.ws_col,
+        height: info.ws_row,
+
+        pixel_width: info.ws_xpixel,
+        pixel_height: info.ws_ypixel,
+    }
+}
+
+fn size_to_winsize(size: TerminalSize) -> libc::winsize {
+    libc::winsize {
+        ws_col: size.width,
+        ws_row: size.height,
+        ws_xpixel: size.pixel_width,
+        ws_ypixel: size.pixel_height,
+    }
+}
+
 fn get_terminal_attr(fd: RawFd) -> Result<libc::termios, io::Error> {
     let mut termios: libc::termios = unsafe { mem::zeroed() };
     wrap_error(unsafe { libc::tcgetattr(fd, &mut termios) })?;
@@ -103,12 +794,24 @@ fn get_terminal_attr(fd: RawFd) -> Result<libc::termios, io::Error> {
     Ok(termios)
 }
 
-fn set_terminal_attr(fd: RawFd, termios: &libc::termios) -> Result<(), io::Error> {
-    wrap_error(unsafe { libc::tcsetattr(fd, libc::TCSANOW, termios) })?;
+fn set_terminal_attr(
+    fd: RawFd,
+    action: libc::c_int,
+    termios: &libc::termios,
+) -> Result<(), io::Error> {
+    wrap_error(unsafe { libc::tcsetattr(fd, action, termios) })?;
 
     Ok(())
 }
 
+fn optional_actions(timing: ApplyTiming) -> libc::c_int {
+    match timing {
+        ApplyTiming::Now => libc::TCSANOW,
+        ApplyTiming::Drain => libc::TCSADRAIN,
+        ApplyTiming::Flush => libc::TCSAFLUSH,
+    }
+}
+
 fn wrap_error(result: libc::c_int) -> io::Result<()> {
     if result == -1 {
         Err(io::Error::last_os_error())
@@ -1,19 +1,24 @@
 use std::io;
+use std::time::{Duration, Instant};
 
 use windows::core::w;
-use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, WAIT_OBJECT_0};
 use windows::Win32::Storage::FileSystem::{
-    CreateFileW, FILE_FLAGS_AND_ATTRIBUTES, FILE_GENERIC_READ, FILE_GENERIC_WRITE, FILE_SHARE_READ,
-    FILE_SHARE_WRITE, OPEN_EXISTING,
+    CreateFileW, ReadFile, WriteFile, FILE_FLAGS_AND_ATTRIBUTES, FILE_GENERIC_READ,
+    FILE_GENERIC_WRITE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
 };
 use windows::Win32::System::Console::{
-    GetConsoleMode, GetConsoleScreenBufferInfo, SetConsoleMode, CONSOLE_MODE,
-    CONSOLE_SCREEN_BUFFER_INFO, ENABLE_ECHO_INPUT, ENABLE_EXTENDED_FLAGS, ENABLE_INSERT_MODE,
-    ENABLE_LINE_INPUT, ENABLE_MOUSE_INPUT, ENABLE_PROCESSED_INPUT, ENABLE_QUICK_EDIT_MODE,
-    ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_WINDOW_INPUT,
+    FlushConsoleInputBuffer, GetConsoleMode, GetConsoleScreenBufferInfo, GetStdHandle,
+    PeekConsoleInputW, ReadConsoleInputW, SetConsoleMode, CONSOLE_MODE, CONSOLE_SCREEN_BUFFER_INFO,
+    ENABLE_ECHO_INPUT, ENABLE_EXTENDED_FLAGS, ENABLE_INSERT_MODE, ENABLE_LINE_INPUT,
+    ENABLE_MOUSE_INPUT, ENABLE_PROCESSED_INPUT, ENABLE_QUICK_EDIT_MODE,
+    ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_WINDOW_INPUT, INPUT_RECORD, KEY_EVENT, STD_ERROR_HANDLE,
+    STD_INPUT_HANDLE, STD_OUTPUT_HANDLE,
 };
+use windows::Win32::System::Threading::WaitForSingleObject;
 
-use crate::TerminalSize;
+use crate::size::StdStream;
+use crate::{ApplyTiming, RawModeOptions, TerminalMode, TerminalSize};
 
 const RAW_MODE_MASK: CONSOLE_MODE = CONSOLE_MODE(
     ENABLE_EXTENDED_FLAGS.0
@@ -30,12 +35,68 @@ const NOT_RAW_MODE_MASK: CONSOLE_MODE = CONSOLE_MODE(
         | ENABLE_PROCESSED_INPUT.0,
 );
 
+pub type RawHandle = HANDLE;
+
+#[derive(Debug)]
+pub struct Handle {
+    input: HANDLE,
+    output: HANDLE,
+}
+
+impl Handle {
+    pub fn tty() -> Result<Self, io::Error> {
+        let input = get_current_in_handle()?;
+        let output = get_current_out_handle().inspect_err(|_| unsafe {
+            let _ = CloseHandle(input);
+        })?;
+
+        Ok(Self { input, output })
+    }
+
+    pub fn try_clone(&self) -> Result<Self, io::Error> {
+        Self::tty()
+    }
+
+    pub fn raw(&self) -> RawHandle {
+        self.input
+    }
+
+    /// Every handle refers to the console of the process.
+    pub fn device_id(&self) -> u64 {
+        0
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.input);
+            let _ = CloseHandle(self.output);
+        }
+    }
+}
+
+/// The settings of a terminal, as saved by the mode guards.
 #[derive(Debug, Clone, Copy)]
 pub struct TerminalState(CONSOLE_MODE);
 
-pub fn size() -> Result<TerminalSize, io::Error> {
-    let handle = get_current_out_handle()?;
-    let info = get_screen_buffer_info(&handle)?;
+pub fn size(handle: &Handle) -> Result<TerminalSize, io::Error> {
+    screen_size(&handle.output)
+}
+
+/// Standard input is a console input handle without a screen buffer, so it never has a size.
+pub fn std_stream_size(stream: StdStream) -> Result<TerminalSize, io::Error> {
+    let handle = match stream {
+        StdStream::Stdout => unsafe { GetStdHandle(STD_OUTPUT_HANDLE)? },
+        StdStream::Stderr => unsafe { GetStdHandle(STD_ERROR_HANDLE)? },
+        StdStream::Stdin => unsafe { GetStdHandle(STD_INPUT_HANDLE)? },
+    };
+
+    screen_size(&handle)
+}
+
+fn screen_size(handle: &HANDLE) -> Result<TerminalSize, io::Error> {
+    let info = get_screen_buffer_info(handle)?;
 
     let width = info.srWindow.Right - info.srWindow.Left + 1;
     let height = info.srWindow.Bottom - info.srWindow.Top + 1;
@@ -47,56 +108,187 @@ pub fn size() -> Result<TerminalSize, io::Error> {
     })
 }
 
-pub fn is_raw_mode_enabled() -> Result<bool, io::Error> {
-    let handle = get_current_in_handle()?;
-    let mode = get_console_mode(&handle)?;
+pub fn current_mode(handle: &Handle) -> Result<TerminalMode, io::Error> {
+    let mode = get_console_mode(&handle.input)?;
 
-    Ok(mode & NOT_RAW_MODE_MASK == CONSOLE_MODE(0) && mode & RAW_MODE_MASK == RAW_MODE_MASK)
+    let mode = if mode & ENABLE_LINE_INPUT == CONSOLE_MODE(0) {
+        // Raw mode can keep Ctrl-C processing, but always enables virtual terminal input.
+        if mode & ENABLE_PROCESSED_INPUT == CONSOLE_MODE(0)
+            || mode & ENABLE_VIRTUAL_TERMINAL_INPUT != CONSOLE_MODE(0)
+        {
+            TerminalMode::Raw
+        } else {
+            TerminalMode::Cbreak
+        }
+    } else if mode & ENABLE_ECHO_INPUT == CONSOLE_MODE(0) {
+        TerminalMode::NoEcho
+    } else {
+        TerminalMode::Normal
+    };
+
+    Ok(mode)
 }
 
-pub fn enable_raw_mode() -> Result<TerminalState, io::Error> {
-    let handle = get_current_in_handle()?;
-    let original_mode = get_console_mode(&handle)?;
+pub fn enable_raw_mode(
+    handle: &Handle,
+    options: &RawModeOptions,
+) -> Result<TerminalState, io::Error> {
+    update_console_mode(handle, options.timing, |mode| {
+        let mut new_mode = mode & !NOT_RAW_MODE_MASK | RAW_MODE_MASK;
+        if options.signals {
+            new_mode |= mode & ENABLE_PROCESSED_INPUT;
+        }
+        new_mode
+    })
+}
 
-    let new_mode = original_mode & !NOT_RAW_MODE_MASK | RAW_MODE_MASK;
-    set_console_mode(&handle, new_mode)?;
+pub fn enable_cbreak_mode(handle: &Handle) -> Result<TerminalState, io::Error> {
+    update_console_mode(handle, ApplyTiming::Now, |mode| {
+        mode & !(ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT)
+    })
+}
 
-    Ok(TerminalState(original_mode))
+pub fn enable_no_echo_mode(handle: &Handle) -> Result<TerminalState, io::Error> {
+    update_console_mode(handle, ApplyTiming::Now, |mode| mode & !ENABLE_ECHO_INPUT)
 }
 
-pub fn restore_mode(original_mode: TerminalState) -> Result<(), io::Error> {
-    let handle = get_current_in_handle()?;
-    set_console_mode(&handle, original_mode.0)?;
+pub fn write_all(handle: &Handle, mut bytes: &[u8]) -> Result<(), io::Error> {
+    while !bytes.is_empty() {
+        let mut written = 0;
+        unsafe { WriteFile(handle.output, Some(bytes), Some(&mut written), None)? };
+        bytes = &bytes[written as usize..];
+    }
 
     Ok(())
 }
 
-// TODO: check if there is a better way in windows to get notified when the terminal is resized
-#[cfg(feature = "tokio")]
-pub fn spawn_on_resize_task(
-    tx: tokio::sync::watch::Sender<TerminalSize>,
-) -> Result<tokio::task::JoinHandle<()>, io::Error> {
-    let task = tokio::spawn(async move {
-        loop {
-            if tx.is_closed() {
-                break;
-            }
-
-            if let Ok(size) = size() {
-                tx.send_if_modified(|current_size| {
-                    if current_size != &size {
-                        *current_size = size;
-                        true
-                    } else {
-                        false
-                    }
-                });
-            };
-
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+/// Reads whatever input arrives within `timeout`. Returns `None` if nothing did and
+/// `Some(0)` at the end of the input.
+pub fn read_timeout(
+    handle: &Handle,
+    buffer: &mut [u8],
+    timeout: Duration,
+) -> Result<Option<usize>, io::Error> {
+    let deadline = Instant::now() + timeout;
+    // Focus, mouse and key up events signal the console as well, but `ReadFile` only
+    // returns once a key produced a character.
+    while !take_key_input(handle)? {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let timeout = remaining.as_millis().min(u128::from(u32::MAX - 1)) as u32;
+        if unsafe { WaitForSingleObject(handle.input, timeout) } != WAIT_OBJECT_0 {
+            return Ok(None);
         }
-    });
-    Ok(task)
+    }
+
+    let mut read = 0;
+    unsafe { ReadFile(handle.input, Some(buffer), Some(&mut read), None)? };
+    Ok(Some(read as usize))
+}
+
+/// Tells whether a pending input event produced a character, discarding the events before
+/// it that did not.
+fn take_key_input(handle: &Handle) -> Result<bool, io::Error> {
+    let mut records = [INPUT_RECORD::default(); 64];
+    let mut count = 0;
+    unsafe { PeekConsoleInputW(handle.input, &mut records, &mut count)? };
+
+    let records = &mut records[..count as usize];
+    let skipped = records
+        .iter()
+        .take_while(|record| !is_character(record))
+        .count();
+    if skipped > 0 {
+        unsafe { ReadConsoleInputW(handle.input, &mut records[..skipped], &mut count)? };
+    }
+
+    Ok(skipped < records.len())
+}
+
+fn is_character(record: &INPUT_RECORD) -> bool {
+    if u32::from(record.EventType) != KEY_EVENT {
+        return false;
+    }
+    let key = unsafe { record.Event.KeyEvent };
+    key.bKeyDown.as_bool() && unsafe { key.uChar.UnicodeChar } != 0
+}
+
+pub fn restore_mode(handle: &Handle, original_mode: TerminalState) -> Result<(), io::Error> {
+    set_console_mode(&handle.input, original_mode.0)?;
+
+    Ok(())
+}
+
+pub fn restore_mode_with(
+    handle: &Handle,
+    original_mode: TerminalState,
+    timing: ApplyTiming,
+) -> Result<(), io::Error> {
+    set_console_mode(&handle.input, original_mode.0)?;
+    if timing == ApplyTiming::Flush {
+        flush_input(handle)?;
+    }
+
+    Ok(())
+}
+
+pub fn flush_input(handle: &Handle) -> Result<(), io::Error> {
+    unsafe { FlushConsoleInputBuffer(handle.input)? }
+
+    Ok(())
+}
+
+/// Console output is written synchronously, there is nothing to wait for.
+pub fn drain_output(_handle: &Handle) -> Result<(), io::Error> {
+    Ok(())
+}
+
+pub fn restore_raw(handle: RawHandle, original_mode: TerminalState) -> Result<(), io::Error> {
+    set_console_mode(&handle, original_mode.0)
+}
+
+/// Tells whether the current thread is the main thread of the process, going by the name the
+/// standard library gives it.
+pub fn is_main_thread() -> bool {
+    std::thread::current().name() == Some("main")
+}
+
+/// The console has no fatal signals to hook into, only the panic hook applies.
+pub fn install_restore_handlers() -> Result<(), io::Error> {
+    Ok(())
+}
+
+/// How often [`ResizeSignal::wait`] checks the console size.
+const RESIZE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The console has no resize signal, so waiting for one just waits for the next check.
+// TODO: check if there is a better way in windows to get notified when the terminal is resized
+pub struct ResizeSignal(());
+
+impl ResizeSignal {
+    pub fn new() -> Result<Self, io::Error> {
+        Ok(Self(()))
+    }
+
+    pub fn wait(&self) -> Result<(), io::Error> {
+        std::thread::sleep(RESIZE_POLL_INTERVAL);
+        Ok(())
+    }
+}
+
+/// Applies `update` to the current input mode and returns the mode from before.
+fn update_console_mode(
+    handle: &Handle,
+    timing: ApplyTiming,
+    update: impl FnOnce(CONSOLE_MODE) -> CONSOLE_MODE,
+) -> Result<TerminalState, io::Error> {
+    let original_mode = get_console_mode(&handle.input)?;
+
+    set_console_mode(&handle.input, update(original_mode))?;
+    if timing == ApplyTiming::Flush {
+        flush_input(handle)?;
+    }
+
+    Ok(TerminalState(original_mode))
 }
 
 fn get_current_in_handle() -> Result<HANDLE, io::Error> {
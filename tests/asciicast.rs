@@ -0,0 +1,127 @@
+#![cfg(unix)]
+
+use std::io::{ErrorKind, Read, Write};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use terminal_utils::asciicast::Recorder;
+use terminal_utils::passthrough::Passthrough;
+use terminal_utils::{openpty, PtyMaster, TerminalSize};
+
+fn size(width: u16, height: u16) -> TerminalSize {
+    TerminalSize {
+        width,
+        height,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+fn read_until(master: &PtyMaster, expected: &[u8]) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut output = Vec::new();
+    let mut buffer = [0; 256];
+    while !output
+        .windows(expected.len())
+        .any(|window| window == expected)
+    {
+        assert!(Instant::now() < deadline, "read {output:?}");
+        match (&*master).read(&mut buffer) {
+            Ok(n) => output.extend_from_slice(&buffer[..n]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(err) => panic!("read: {err}"),
+        }
+    }
+}
+
+/// Returns the event code and data of an event line, checking its timestamp.
+fn event(line: &str) -> (&str, &str) {
+    let line = line.strip_prefix('[').unwrap().strip_suffix(']').unwrap();
+    let (time, rest) = line.split_once(", ").unwrap();
+    assert!(time.parse::<f64>().unwrap() >= 0.0, "time {time}");
+    let (code, data) = rest.split_once(", ").unwrap();
+
+    (code.trim_matches('"'), data)
+}
+
+#[test]
+fn writes_header_and_events() {
+    let recorder = Recorder::new(Vec::new(), size(80, 24)).unwrap();
+    recorder.output(b"caf\xc3").unwrap();
+    recorder.output(b"\xa9 \"ok\"\r\n").unwrap();
+    recorder.input(b"\x1b[A").unwrap();
+    recorder.resize(size(100, 30)).unwrap();
+    let recording = String::from_utf8(recorder.finish().unwrap()).unwrap();
+
+    let mut lines = recording.lines();
+    let header = lines.next().unwrap();
+    assert!(header.starts_with(r#"{"version": 2, "width": 80, "height": 24, "timestamp": "#));
+    let events: Vec<_> = lines.map(event).collect();
+    assert_eq!(
+        events,
+        [
+            ("o", r#""caf""#),
+            ("o", r#""é \"ok\"\r\n""#),
+            ("i", r#""\u001b[A""#),
+            ("r", r#""100x30""#),
+        ]
+    );
+}
+
+#[test]
+fn finish_records_an_unfinished_sequence() {
+    let recorder = Recorder::new(Vec::new(), size(80, 24)).unwrap();
+    recorder.output(b"ok \xe2\x82").unwrap();
+    recorder.input(b"\xc3").unwrap();
+    let recording = String::from_utf8(recorder.finish().unwrap()).unwrap();
+
+    let events: Vec<_> = recording.lines().skip(1).map(event).collect();
+    assert_eq!(
+        events,
+        [
+            ("o", r#""ok ""#),
+            ("o", "\"\u{fffd}\""),
+            ("i", "\"\u{fffd}\"")
+        ]
+    );
+}
+
+#[test]
+fn records_a_passthrough_session_with_resizes() {
+    let outer = openpty(size(80, 24), None).unwrap();
+    let terminal = outer.slave.try_clone().unwrap();
+    let recorder = Recorder::new(Vec::new(), size(80, 24))
+        .unwrap()
+        .record_input(true);
+    let subscription = recorder.record_resizes(&terminal).unwrap();
+    let session = recorder.attach(Passthrough::new({
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("echo ready; read line; echo \"got $line\"");
+        command
+    }));
+
+    let session = thread::spawn(move || session.run_on(&terminal));
+    read_until(&outer.master, b"ready");
+    outer.slave.set_size(size(100, 30)).unwrap();
+    unsafe { libc::kill(libc::getpid(), libc::SIGWINCH) };
+    (&outer.master).write_all(b"hello\r").unwrap();
+    read_until(&outer.master, b"got hello");
+    assert!(session.join().unwrap().unwrap().success());
+
+    drop(subscription);
+    let recording = String::from_utf8(recorder.finish().unwrap()).unwrap();
+    let events: Vec<_> = recording.lines().skip(1).map(event).collect();
+    assert!(events.contains(&("i", r#""hello\r""#)), "{recording}");
+    assert!(events.contains(&("r", r#""100x30""#)), "{recording}");
+    assert!(
+        events
+            .iter()
+            .any(|&(code, data)| code == "o" && data.contains("got hello")),
+        "{recording}"
+    );
+}
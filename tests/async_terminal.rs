@@ -0,0 +1,60 @@
+#![cfg(all(unix, feature = "tokio"))]
+
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{AsFd, AsRawFd};
+
+use terminal_utils::{openpty, TerminalSize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const SIZE: TerminalSize = TerminalSize {
+    width: 80,
+    height: 24,
+    pixel_width: 0,
+    pixel_height: 0,
+};
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[test]
+fn master_reads_end_of_file_once_the_slave_is_closed() {
+    block_on(async {
+        let pty = openpty(SIZE, None).unwrap();
+        let mut master = pty.master.into_async().unwrap();
+
+        let mut slave = File::from(pty.slave.as_fd().try_clone_to_owned().unwrap());
+        slave.write_all(b"output").unwrap();
+        let mut output = [0; 6];
+        master.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"output");
+
+        drop(slave);
+        drop(pty.slave);
+        let mut rest = Vec::new();
+        assert_eq!(master.read_to_end(&mut rest).await.unwrap(), 0);
+    });
+}
+
+#[test]
+fn terminal_is_reopened_for_nonblocking_writes() {
+    block_on(async {
+        let pty = openpty(SIZE, None).unwrap();
+        let _raw_mode_guard = pty.slave.enable_raw_mode().unwrap();
+        let mut master = pty.master.into_async().unwrap();
+        let mut slave = pty.slave.to_async().unwrap();
+
+        slave.write_all(b"output").await.unwrap();
+        let mut output = [0; 6];
+        master.read_exact(&mut output).await.unwrap();
+        assert_eq!(&output, b"output");
+
+        let flags = unsafe { libc::fcntl(pty.slave.as_raw_fd(), libc::F_GETFL) };
+        assert_eq!(flags & libc::O_NONBLOCK, 0, "the handle stays blocking");
+    });
+}
@@ -0,0 +1,27 @@
+//! Helpers shared by the integration tests.
+//!
+//! Tests that fork are the only test of their file. Each file runs as its own process, so no
+//! other test thread can hold a lock, like the one of the allocator, while the child is forked
+//! and the child cannot deadlock. The same goes for tests that count the threads or the open
+//! descriptors of the process.
+
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::{io, ptr};
+
+/// Opens a pseudo-terminal and returns its master and slave.
+pub fn openpty() -> (OwnedFd, OwnedFd) {
+    let mut master = -1;
+    let mut slave = -1;
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    assert_eq!(result, 0, "openpty: {}", io::Error::last_os_error());
+
+    unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) }
+}
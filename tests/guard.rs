@@ -0,0 +1,167 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use common::openpty;
+use terminal_utils::{ApplyTiming, RawModeOptions, Terminal, TerminalMode};
+
+#[test]
+fn restore_returns_to_the_previous_mode() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let guard = terminal.enable_raw_mode().unwrap();
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Raw);
+
+    guard.restore().unwrap();
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+}
+
+#[test]
+fn raw_mode_with_signals_is_detected() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let options = RawModeOptions::new().signals(true);
+    let guard = terminal.enable_raw_mode_with(&options).unwrap();
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Raw);
+    assert!(terminal.is_raw_mode_enabled().unwrap());
+    guard.restore().unwrap();
+
+    let _guard = terminal.enable_cbreak_mode().unwrap();
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Cbreak);
+}
+
+#[test]
+fn original_state_is_the_state_before_the_guard() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let guard = terminal.enable_raw_mode().unwrap();
+    let original_state = guard.original_state();
+    guard.restore().unwrap();
+
+    let guard = terminal.enable_no_echo_mode().unwrap();
+    assert_eq!(
+        format!("{:?}", guard.original_state()),
+        format!("{original_state:?}")
+    );
+}
+
+#[test]
+fn restore_fails_out_of_order() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let outer = terminal.enable_cbreak_mode().unwrap();
+    let inner = terminal.enable_raw_mode().unwrap();
+
+    assert!(outer.restore().is_err());
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Raw);
+
+    inner.restore().unwrap();
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+}
+
+#[test]
+fn restore_fails_after_hangup() {
+    let (master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let guard = terminal.enable_raw_mode().unwrap();
+    drop(master);
+
+    assert!(guard.restore().is_err());
+}
+
+/// Fills the pty with newlines until the writer blocks, then returns the writer thread and a
+/// reader that only starts to read the output after a while, so the mode can change first.
+fn write_until_blocked(master: OwnedFd, slave: &OwnedFd) -> (JoinHandle<()>, JoinHandle<Vec<u8>>) {
+    const NEWLINES: usize = 256 * 1024;
+
+    let mut slave = File::from(slave.try_clone().unwrap());
+    let writer = thread::spawn(move || slave.write_all(&[b'\n'; NEWLINES]).unwrap());
+    thread::sleep(Duration::from_millis(100));
+    assert!(!writer.is_finished(), "the pty took all output at once");
+
+    let reader = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        let mut master = File::from(master);
+        let mut output = Vec::new();
+        let mut buffer = [0; 4096];
+        while output.iter().filter(|&&byte| byte == b'\n').count() < NEWLINES {
+            let read = master.read(&mut buffer).unwrap();
+            output.extend_from_slice(&buffer[..read]);
+        }
+        output
+    });
+
+    (writer, reader)
+}
+
+#[test]
+fn drop_applies_the_previous_mode_after_pending_output() {
+    let (master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let guard = terminal.enable_raw_mode().unwrap();
+    let (writer, reader) = write_until_blocked(master, &slave);
+    drop(guard);
+    assert!(
+        writer.is_finished(),
+        "the mode changed before the output was written"
+    );
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+
+    // written without output processing, so no newline is turned into `\r\n`
+    writer.join().unwrap();
+    assert!(!reader.join().unwrap().contains(&b'\r'));
+}
+
+#[test]
+fn now_timing_applies_the_previous_mode_before_pending_output() {
+    let (master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let guard = terminal.enable_raw_mode().unwrap();
+    let (writer, reader) = write_until_blocked(master, &slave);
+    guard.restore_with(ApplyTiming::Now).unwrap();
+    assert!(
+        !writer.is_finished(),
+        "the mode was applied after the output"
+    );
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+
+    // the rest of the output is processed with the previous settings
+    writer.join().unwrap();
+    assert!(reader.join().unwrap().contains(&b'\r'));
+}
+
+#[test]
+fn flush_timing_discards_typed_ahead_input() {
+    let (master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    let mut master = File::from(master);
+
+    master.write_all(b"typed ahead\n").unwrap();
+    // the pty hands input to the slave asynchronously, it has to be queued before the flush
+    let mut pollfd = libc::pollfd {
+        fd: slave.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 5000) }, 1);
+    let options = RawModeOptions::new().timing(ApplyTiming::Flush);
+    let _guard = terminal.enable_raw_mode_with(&options).unwrap();
+    master.write_all(b"x").unwrap();
+
+    let mut input = [0; 1];
+    File::from(slave).read_exact(&mut input).unwrap();
+    assert_eq!(&input, b"x");
+}
@@ -0,0 +1,106 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+use std::{io, thread};
+
+use common::openpty;
+use terminal_utils::{Terminal, TerminalMode};
+
+/// Waits for the next change of the child, as selected by `options`.
+fn wait(pid: libc::pid_t, options: libc::c_int) -> libc::c_int {
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(pid, &mut status, options) }, pid);
+    status
+}
+
+/// Waits until the pty is in `mode`, which the child applies after it continued.
+fn wait_for_mode(terminal: &Terminal, mode: TerminalMode) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while terminal.current_mode().unwrap() != mode {
+        assert!(Instant::now() < deadline, "the pty never became {mode:?}");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Reads a byte the child wrote, `None` once it closed the pipe.
+fn read_byte(fd: &OwnedFd) -> Option<u8> {
+    let mut byte = 0u8;
+    let read = unsafe { libc::read(fd.as_raw_fd(), (&mut byte as *mut u8).cast(), 1) };
+    (read == 1).then_some(byte)
+}
+
+#[test]
+fn restores_on_stop_and_reapplies_on_continue() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    let before = terminal.snapshot().unwrap();
+
+    let mut fds = [-1; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let (ready_rx, ready_tx) =
+        unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork: {}", io::Error::last_os_error()),
+        0 => {
+            // A stop signal is discarded in an orphaned process group, which the group of
+            // the test runner may be.
+            unsafe { libc::setpgid(0, 0) };
+
+            // no tokio runtime is running, the resume notifications do not need one
+            let guard = terminal
+                .enable_raw_mode()
+                .unwrap()
+                .with_job_control()
+                .unwrap();
+            unsafe { libc::write(ready_tx.as_raw_fd(), b"r".as_ptr().cast(), 1) };
+
+            #[cfg(feature = "tokio")]
+            {
+                let resume_rx = guard.on_resume();
+                while !resume_rx.has_changed().unwrap() {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                unsafe { libc::write(ready_tx.as_raw_fd(), b"c".as_ptr().cast(), 1) };
+            }
+            let _guard = guard;
+            loop {
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+        pid => {
+            drop(ready_tx);
+            assert_eq!(
+                read_byte(&ready_rx),
+                Some(b'r'),
+                "the child did not enable job control"
+            );
+            assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Raw);
+
+            unsafe { libc::kill(pid, libc::SIGTSTP) };
+            let status = wait(pid, libc::WUNTRACED);
+            assert!(libc::WIFSTOPPED(status), "the child did not stop");
+            assert_eq!(libc::WSTOPSIG(status), libc::SIGTSTP);
+            assert!(
+                before.diff(&terminal.snapshot().unwrap()).is_empty(),
+                "the pty was not restored before the child stopped"
+            );
+
+            unsafe { libc::kill(pid, libc::SIGCONT) };
+            assert!(libc::WIFCONTINUED(wait(pid, libc::WCONTINUED)));
+            wait_for_mode(&terminal, TerminalMode::Raw);
+            #[cfg(feature = "tokio")]
+            assert_eq!(
+                read_byte(&ready_rx),
+                Some(b'c'),
+                "the child was not notified"
+            );
+
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            assert!(libc::WIFSIGNALED(wait(pid, 0)));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+#![cfg(unix)]
+
+use std::io::{ErrorKind, Read, Write};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use terminal_utils::passthrough::Passthrough;
+use terminal_utils::{openpty, PtyMaster, Terminal, TerminalSize};
+
+const SIZE: TerminalSize = TerminalSize {
+    width: 80,
+    height: 24,
+    pixel_width: 0,
+    pixel_height: 0,
+};
+
+fn read_until(master: &PtyMaster, expected: &[u8]) -> Vec<u8> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut output = Vec::new();
+    let mut buffer = [0; 256];
+    while !output
+        .windows(expected.len())
+        .any(|window| window == expected)
+    {
+        assert!(Instant::now() < deadline, "read {output:?}");
+        match (&*master).read(&mut buffer) {
+            Ok(n) => output.extend_from_slice(&buffer[..n]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            Err(err) => panic!("read: {err}"),
+        }
+    }
+
+    output
+}
+
+#[test]
+fn copies_both_ways_and_returns_the_exit_status() {
+    // The outer pseudo-terminal stands in for the terminal of the user.
+    let outer = openpty(SIZE, None).unwrap();
+    let terminal = outer.slave.try_clone().unwrap();
+    let output_log = Arc::new(Mutex::new(Vec::new()));
+    let log = output_log.clone();
+
+    let session = thread::spawn(move || {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("stty size; read line; echo \"got $line\"; exit 3");
+        Passthrough::new(command)
+            .on_input(|bytes| bytes.make_ascii_uppercase())
+            .on_output(move |bytes| log.lock().unwrap().extend_from_slice(bytes))
+            .run_on(&terminal)
+    });
+
+    read_until(&outer.master, b"24 80");
+    assert!(outer.slave.is_raw_mode_enabled().unwrap());
+    (&outer.master).write_all(b"hello\r").unwrap();
+    read_until(&outer.master, b"got HELLO");
+
+    let status = session.join().unwrap().unwrap();
+    assert_eq!(status.code(), Some(3));
+    assert!(!outer.slave.is_raw_mode_enabled().unwrap());
+
+    let log = output_log.lock().unwrap();
+    assert!(log.windows(9).any(|window| window == b"got HELLO"));
+}
+
+#[test]
+fn fails_for_a_terminal_that_is_not_a_tty() {
+    let file = std::fs::File::open("/dev/null").unwrap();
+    let terminal = Terminal::from_fd(&file).unwrap();
+
+    let result = Passthrough::new(Command::new("true")).run_on(&terminal);
+    assert!(result.is_err());
+}
+
+#[test]
+fn keeps_running_after_the_terminal_hangs_up() {
+    let outer = openpty(SIZE, None).unwrap();
+    let terminal = outer.slave.try_clone().unwrap();
+
+    let session = thread::spawn(move || {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo ready; sleep 1; exit 4");
+        let status = Passthrough::new(command).run_on(&terminal);
+
+        let mut cpu_time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut cpu_time) };
+        (status, cpu_time)
+    });
+
+    read_until(&outer.master, b"ready");
+    drop(outer.master);
+
+    let (status, cpu_time) = session.join().unwrap();
+    assert_eq!(status.unwrap().code(), Some(4));
+    assert!(
+        cpu_time.tv_sec == 0 && cpu_time.tv_nsec < 500_000_000,
+        "the session spun on the hung up terminal"
+    );
+}
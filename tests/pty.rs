@@ -0,0 +1,168 @@
+#![cfg(unix)]
+
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsFd, AsRawFd};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use terminal_utils::{
+    openpty, PtyCommand, PtyMaster, ResizeForwarding, TerminalMode, TerminalSize,
+};
+
+const SIZE: TerminalSize = TerminalSize {
+    width: 100,
+    height: 30,
+    pixel_width: 1000,
+    pixel_height: 600,
+};
+
+fn read_until(master: &PtyMaster, expected: &[u8]) -> Vec<u8> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut output = Vec::new();
+    let mut buffer = [0; 256];
+    while !output
+        .windows(expected.len())
+        .any(|window| window == expected)
+    {
+        assert!(Instant::now() < deadline, "read {output:?}");
+        match (&*master).read(&mut buffer) {
+            Ok(n) => output.extend_from_slice(&buffer[..n]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10))
+            }
+            Err(err) => panic!("read: {err}"),
+        }
+    }
+
+    output
+}
+
+fn fd_flags(fd: &impl AsRawFd) -> (libc::c_int, libc::c_int) {
+    let fd = fd.as_raw_fd();
+    unsafe {
+        (
+            libc::fcntl(fd, libc::F_GETFD),
+            libc::fcntl(fd, libc::F_GETFL),
+        )
+    }
+}
+
+#[test]
+fn starts_with_the_given_size() {
+    let pty = openpty(SIZE, None).unwrap();
+    assert_eq!(pty.slave.size().unwrap(), SIZE);
+    assert_eq!(pty.master.size().unwrap(), SIZE);
+
+    let size = TerminalSize {
+        width: 120,
+        height: 40,
+        ..SIZE
+    };
+    pty.master.set_size(size).unwrap();
+    assert_eq!(pty.slave.size().unwrap(), size);
+}
+
+#[test]
+fn copies_the_given_settings() {
+    let parent = openpty(SIZE, None).unwrap();
+    let _raw_mode_guard = parent.slave.enable_raw_mode().unwrap();
+    let settings = parent.slave.snapshot().unwrap();
+
+    let pty = openpty(SIZE, Some(&settings)).unwrap();
+    assert_eq!(pty.slave.current_mode().unwrap(), TerminalMode::Raw);
+    assert!(settings.diff(&pty.slave.snapshot().unwrap()).is_empty());
+}
+
+#[test]
+fn sets_close_on_exec_and_nonblocking() {
+    let pty = openpty(SIZE, None).unwrap();
+
+    let (fd_flags_master, status_flags_master) = fd_flags(&pty.master);
+    assert_ne!(fd_flags_master & libc::FD_CLOEXEC, 0);
+    assert_ne!(status_flags_master & libc::O_NONBLOCK, 0);
+
+    let (fd_flags_slave, status_flags_slave) = fd_flags(&pty.slave);
+    assert_ne!(fd_flags_slave & libc::FD_CLOEXEC, 0);
+    assert_eq!(status_flags_slave & libc::O_NONBLOCK, 0);
+
+    let mut buffer = [0; 16];
+    let err = (&pty.master).read(&mut buffer).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+}
+
+#[test]
+fn master_and_slave_are_connected() {
+    let mut pty = openpty(SIZE, None).unwrap();
+    let _raw_mode_guard = pty.slave.enable_raw_mode().unwrap();
+
+    pty.master.write_all(b"input").unwrap();
+    let mut slave = File::from(pty.slave.as_fd().try_clone_to_owned().unwrap());
+    let mut input = [0; 5];
+    slave.read_exact(&mut input).unwrap();
+    assert_eq!(&input, b"input");
+
+    slave.write_all(b"output").unwrap();
+    assert_eq!(read_until(&pty.master, b"output"), b"output");
+}
+
+fn shell(script: &str) -> PtyCommand {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(script);
+    PtyCommand::new(command, SIZE)
+}
+
+#[test]
+fn child_runs_on_the_pty() {
+    // The child waits for input, the output it leaves behind is discarded when it exits.
+    let mut child = shell("stty size; exec 3</dev/tty && echo controlling; read line")
+        .spawn()
+        .unwrap();
+
+    let output = read_until(child.master(), b"controlling");
+    assert!(output.starts_with(b"30 100"), "read {output:?}");
+    child.master().write_all(b"\n").unwrap();
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn child_is_notified_of_resizes() {
+    let mut child = shell("trap 'stty size' WINCH; echo ready; while :; do sleep 0.05; done")
+        .spawn()
+        .unwrap();
+    read_until(child.master(), b"ready");
+
+    child
+        .resize(TerminalSize {
+            width: 120,
+            height: 40,
+            ..SIZE
+        })
+        .unwrap();
+    read_until(child.master(), b"40 120");
+
+    child.kill().unwrap();
+    assert!(!child.wait().unwrap().success());
+}
+
+#[test]
+fn forwarding_scales_and_offsets_the_size() {
+    let forwarding = ResizeForwarding::new().scale(0.5, 1.0).offset(-1, -2);
+    assert_eq!(
+        forwarding.apply(SIZE),
+        TerminalSize {
+            width: 49,
+            height: 28,
+            pixel_width: 490,
+            pixel_height: 560,
+        }
+    );
+
+    let tiny = TerminalSize {
+        width: 1,
+        height: 1,
+        pixel_width: 0,
+        pixel_height: 0,
+    };
+    assert_eq!(forwarding.apply(tiny), tiny);
+}
@@ -0,0 +1,84 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::openpty;
+use terminal_utils::{Terminal, TerminalMode, TerminalSize};
+
+const SIZE: TerminalSize = TerminalSize {
+    width: 80,
+    height: 24,
+    pixel_width: 0,
+    pixel_height: 0,
+};
+
+#[test]
+fn fills_in_the_pixel_size_from_the_replies() {
+    let (master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(SIZE).unwrap();
+
+    let mut master = File::from(master);
+    let responder = thread::spawn(move || {
+        let mut query = Vec::new();
+        let mut buffer = [0; 64];
+        while !query.ends_with(b"\x1b[c") {
+            let read = master.read(&mut buffer).unwrap();
+            query.extend_from_slice(&buffer[..read]);
+        }
+        master
+            .write_all(b"typed\x1b[4;480;800t\x1b[6;20;10t\x1b[8;24;80t\x1b[?62;22c")
+            .unwrap();
+        (query, master)
+    });
+
+    let size = terminal.query_pixel_size(Duration::from_secs(5)).unwrap();
+    let (query, _master) = responder.join().unwrap();
+
+    assert_eq!(query, b"\x1b[14t\x1b[16t\x1b[18t\x1b[c");
+    assert_eq!((size.width, size.height), (80, 24));
+    assert_eq!((size.pixel_width, size.pixel_height), (800, 480));
+    assert_eq!(size.cell_width_px(), Some(10));
+    assert_eq!(size.cell_height_px(), Some(20));
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+}
+
+#[test]
+fn gives_up_after_the_timeout() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(SIZE).unwrap();
+
+    let start = Instant::now();
+    let size = terminal
+        .query_pixel_size(Duration::from_millis(100))
+        .unwrap();
+
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!((size.pixel_width, size.pixel_height), (0, 0));
+    assert_eq!(size.cell_width_px(), None);
+}
+
+#[test]
+fn stops_waiting_once_the_terminal_hangs_up() {
+    let (master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(SIZE).unwrap();
+
+    let mut master = File::from(master);
+    let hangup = thread::spawn(move || {
+        let mut buffer = [0; 64];
+        let _ = master.read(&mut buffer).unwrap();
+    });
+
+    let start = Instant::now();
+    let _ = terminal.query_pixel_size(Duration::from_secs(5));
+    hangup.join().unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
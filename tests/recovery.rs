@@ -0,0 +1,56 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::ffi::CStr;
+use std::os::fd::AsRawFd;
+use std::process::Command;
+use std::{env, io, mem};
+
+use common::openpty;
+use terminal_utils::{Terminal, TerminalMode};
+
+#[test]
+fn recovers_the_state_of_a_killed_process() {
+    let runtime_dir = env::temp_dir().join(format!("terminal-utils-test-{}", std::process::id()));
+    env::set_var("XDG_RUNTIME_DIR", &runtime_dir);
+
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    let path = unsafe { CStr::from_ptr(libc::ttyname(slave.as_raw_fd())) }
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    let err = terminal.recover().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork: {}", io::Error::last_os_error()),
+        0 => {
+            terminal_utils::set_persist_state(true);
+            mem::forget(terminal.enable_raw_mode().unwrap());
+            unsafe { libc::raise(libc::SIGKILL) };
+            unreachable!();
+        }
+        pid => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+            assert_eq!(libc::WTERMSIG(status), libc::SIGKILL);
+        }
+    }
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Raw);
+
+    let status = Command::new(env!("CARGO_BIN_EXE_terminal-utils"))
+        .args(["recover", &path])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+
+    // the state file is gone once recovered
+    let err = terminal.recover().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+    std::fs::remove_dir_all(runtime_dir).unwrap();
+}
@@ -0,0 +1,222 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::os::fd::AsRawFd;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use common::openpty;
+use terminal_utils::{ResizeForwarding, Terminal, TerminalSize};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn size(width: u16, height: u16) -> TerminalSize {
+    TerminalSize {
+        width,
+        height,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+/// Resizes the pty and sends `SIGWINCH` like the kernel would to its foreground process.
+fn resize(terminal: &Terminal, size: TerminalSize) {
+    terminal.set_size(size).unwrap();
+    unsafe { libc::kill(libc::getpid(), libc::SIGWINCH) };
+}
+
+#[test]
+fn channel_receives_the_new_size() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let resize_rx = terminal.resize_channel().unwrap();
+    resize(&terminal, size(100, 30));
+    assert_eq!(resize_rx.recv_timeout(TIMEOUT), Ok(size(100, 30)));
+
+    resize(&terminal, size(120, 40));
+    assert_eq!(resize_rx.recv_timeout(TIMEOUT), Ok(size(120, 40)));
+}
+
+#[test]
+fn callback_stops_once_the_subscription_is_dropped() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let subscription = terminal
+        .on_resize_callback(move |size| tx.send(size).unwrap())
+        .unwrap();
+    resize(&terminal, size(100, 30));
+    assert_eq!(rx.recv_timeout(TIMEOUT), Ok(size(100, 30)));
+
+    drop(subscription);
+    resize(&terminal, size(120, 40));
+    assert!(
+        rx.recv_timeout(TIMEOUT).is_err(),
+        "the callback was dropped"
+    );
+}
+
+#[test]
+fn dropping_the_subscription_waits_for_a_running_callback() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let captured = Arc::new(());
+    let callback_captured = Arc::clone(&captured);
+    let (tx, rx) = mpsc::channel();
+    let subscription = terminal
+        .on_resize_callback(move |_| {
+            let _captured = &callback_captured;
+            tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(200));
+        })
+        .unwrap();
+    resize(&terminal, size(100, 30));
+    rx.recv_timeout(TIMEOUT).unwrap();
+
+    drop(subscription);
+    assert_eq!(
+        Arc::strong_count(&captured),
+        1,
+        "the callback is still alive"
+    );
+}
+
+#[test]
+fn fd_becomes_readable() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let resize_fd = terminal.resize_fd().unwrap();
+    assert_eq!(resize_fd.take(), None);
+
+    resize(&terminal, size(100, 30));
+    let mut pollfd = libc::pollfd {
+        fd: resize_fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ready = unsafe { libc::poll(&mut pollfd, 1, TIMEOUT.as_millis() as libc::c_int) };
+    assert_eq!(ready, 1);
+
+    assert_eq!(resize_fd.take(), Some(size(100, 30)));
+    assert_eq!(resize_fd.take(), None);
+}
+
+#[test]
+fn debounced_events_report_the_settled_size() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let events = terminal
+        .on_resize_debounced(Duration::from_millis(200))
+        .unwrap();
+    for width in 81..=90 {
+        resize(&terminal, size(width, 24));
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let event = events.recv_timeout(TIMEOUT).unwrap();
+    assert_eq!(event.old, size(80, 24));
+    assert_eq!(event.new, size(90, 24));
+    assert!(event.columns_changed());
+    assert!(!event.rows_changed());
+    assert!(!event.pixels_changed());
+    assert!(events.recv_timeout(Duration::from_millis(400)).is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn watch_receiver_clones_keep_receiving() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let wait_for = |resize_rx: &tokio::sync::watch::Receiver<TerminalSize>, expected| {
+        let deadline = std::time::Instant::now() + TIMEOUT;
+        while *resize_rx.borrow() != expected && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(*resize_rx.borrow(), expected);
+    };
+
+    let resize_rx = terminal.on_resize().unwrap();
+    resize(&terminal, size(100, 30));
+    wait_for(&resize_rx, size(100, 30));
+
+    let clone_rx = resize_rx.clone();
+    drop(resize_rx);
+    resize(&terminal, size(120, 40));
+    wait_for(&clone_rx, size(120, 40));
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn stream_yields_the_latest_size() {
+    use futures::StreamExt;
+
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let mut resizes = terminal.resize_stream().unwrap();
+    resize(&terminal, size(100, 30));
+    assert_eq!(
+        futures::executor::block_on(resizes.next()),
+        Some(size(100, 30))
+    );
+}
+
+#[cfg(feature = "async-io")]
+#[test]
+fn async_receiver_reports_the_new_size() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let mut resize_rx = terminal.on_resize_async().unwrap();
+    assert_eq!(resize_rx.size(), size(80, 24));
+
+    resize(&terminal, size(100, 30));
+    let new = futures::executor::block_on(resize_rx.changed()).unwrap();
+    assert_eq!(new, size(100, 30));
+    assert_eq!(resize_rx.size(), size(100, 30));
+}
+
+#[test]
+fn forwarding_mirrors_resizes_onto_a_pty() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    terminal.set_size(size(80, 24)).unwrap();
+
+    let pty = terminal_utils::openpty(size(1, 1), None).unwrap();
+    let subscription = ResizeForwarding::new()
+        .offset(0, -1)
+        .start(&terminal, &pty.master)
+        .unwrap();
+    assert_eq!(pty.slave.size().unwrap(), size(80, 23));
+
+    resize(&terminal, size(100, 30));
+    let deadline = std::time::Instant::now() + TIMEOUT;
+    while pty.slave.size().unwrap() != size(100, 29) {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "the pty was not resized"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    drop(subscription);
+    resize(&terminal, size(120, 40));
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(pty.slave.size().unwrap(), size(100, 29));
+}
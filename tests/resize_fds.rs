@@ -0,0 +1,40 @@
+#![cfg(all(target_os = "linux", feature = "tokio"))]
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::openpty;
+use terminal_utils::Terminal;
+
+fn open_fds() -> usize {
+    std::fs::read_dir("/proc/self/fd").unwrap().count()
+}
+
+/// Wakes up the resize listener without changing any size.
+fn wake_listener() {
+    unsafe { libc::kill(libc::getpid(), libc::SIGWINCH) };
+}
+
+#[test]
+fn dropped_watch_receivers_release_their_subscription() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    // starts the resize listener, which keeps its descriptors
+    drop(terminal.on_resize().unwrap());
+    let before = open_fds();
+
+    let receivers: Vec<_> = (0..50).map(|_| terminal.on_resize().unwrap()).collect();
+    drop(receivers);
+    wake_listener();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while open_fds() > before {
+        assert!(
+            Instant::now() < deadline,
+            "{} descriptors instead of {before}",
+            open_fds()
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
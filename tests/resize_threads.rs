@@ -0,0 +1,48 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::openpty;
+use terminal_utils::Terminal;
+
+/// Counts the threads of the process that this crate started, by their truncated name.
+fn threads() -> usize {
+    std::fs::read_dir("/proc/self/task")
+        .unwrap()
+        .filter_map(|task| std::fs::read_to_string(task.ok()?.path().join("comm")).ok())
+        .filter(|name| name.starts_with("terminal-utils-"))
+        .count()
+}
+
+/// Waits until `threads` returns `count`. A new thread names itself after it started.
+fn wait_for_threads(count: usize) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while threads() != count {
+        assert!(
+            Instant::now() < deadline,
+            "{} threads instead of {count}",
+            threads()
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn debounce_thread_ends_once_the_channel_is_dropped() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    // starts the resize listener, which keeps running
+    drop(terminal.resize_channel().unwrap());
+    wait_for_threads(1);
+
+    let events = terminal
+        .on_resize_debounced(Duration::from_millis(10))
+        .unwrap();
+    wait_for_threads(2);
+
+    // Without a further resize, only the dropped subscription can end the thread.
+    drop(events);
+    wait_for_threads(1);
+}
@@ -0,0 +1,80 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{io, mem, panic, process, thread};
+
+use common::openpty;
+
+use terminal_utils::{Terminal, TerminalMode};
+
+/// Forks a child that puts a fresh pty into raw mode, leaks the guard and then runs `die`.
+/// Returns the wait status of the child and the mode the pty was left in.
+fn die_in_raw_mode(install_hooks: bool, die: fn()) -> (libc::c_int, TerminalMode) {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork: {}", io::Error::last_os_error()),
+        0 => {
+            if install_hooks {
+                terminal_utils::install_restore_hooks().unwrap();
+            }
+            mem::forget(terminal.enable_raw_mode().unwrap());
+
+            let _ = panic::catch_unwind(die);
+            unsafe { libc::_exit(0) };
+        }
+        pid => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+
+            (status, terminal.current_mode().unwrap())
+        }
+    }
+}
+
+#[test]
+fn restores_terminal_before_the_process_dies() {
+    let (status, mode) = die_in_raw_mode(false, || {});
+    assert!(libc::WIFEXITED(status));
+    assert_eq!(mode, TerminalMode::Raw, "without hooks the pty stays raw");
+
+    let (status, mode) = die_in_raw_mode(true, || panic!("restore on panic"));
+    assert!(libc::WIFEXITED(status));
+    assert_eq!(mode, TerminalMode::Normal, "panic");
+
+    let (status, mode) = die_in_raw_mode(true, || {
+        let _ = thread::spawn(|| panic!("worker thread")).join();
+    });
+    assert!(libc::WIFEXITED(status));
+    assert_eq!(
+        mode,
+        TerminalMode::Raw,
+        "a panic on another thread leaves the guards alone"
+    );
+
+    let (status, mode) = die_in_raw_mode(true, || process::exit(3));
+    assert_eq!(libc::WEXITSTATUS(status), 3);
+    assert_eq!(mode, TerminalMode::Normal, "process::exit");
+
+    let (status, mode) = die_in_raw_mode(true, || process::abort());
+    assert!(libc::WIFSIGNALED(status));
+    assert_eq!(libc::WTERMSIG(status), libc::SIGABRT);
+    assert_eq!(mode, TerminalMode::Normal, "abort");
+
+    let (status, mode) = die_in_raw_mode(true, || unsafe {
+        libc::raise(libc::SIGTERM);
+    });
+    assert!(libc::WIFSIGNALED(status));
+    assert_eq!(libc::WTERMSIG(status), libc::SIGTERM);
+    assert_eq!(mode, TerminalMode::Normal, "SIGTERM");
+
+    let (status, mode) = die_in_raw_mode(true, || unsafe {
+        libc::raise(libc::SIGHUP);
+    });
+    assert!(libc::WIFSIGNALED(status));
+    assert_eq!(libc::WTERMSIG(status), libc::SIGHUP);
+    assert_eq!(mode, TerminalMode::Normal, "SIGHUP");
+}
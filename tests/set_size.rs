@@ -0,0 +1,62 @@
+// `sigtimedwait` is missing on macOS.
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+mod common;
+
+use std::os::fd::AsRawFd;
+use std::{io, mem, ptr};
+
+use common::openpty;
+use terminal_utils::{Terminal, TerminalSize};
+
+const SIZE: TerminalSize = TerminalSize {
+    width: 120,
+    height: 40,
+    pixel_width: 1200,
+    pixel_height: 800,
+};
+
+#[test]
+fn set_size_updates_the_pty_and_signals_its_foreground_process() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    let mut ready = [-1; 2];
+    assert_eq!(unsafe { libc::pipe(ready.as_mut_ptr()) }, 0);
+
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork: {}", io::Error::last_os_error()),
+        0 => unsafe {
+            // becomes the foreground process of the pty and waits for the resize
+            let mut signals: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut signals);
+            libc::sigaddset(&mut signals, libc::SIGWINCH);
+            libc::sigprocmask(libc::SIG_BLOCK, &signals, ptr::null_mut());
+            libc::setsid();
+            libc::ioctl(slave.as_raw_fd(), libc::TIOCSCTTY, 0);
+            libc::close(ready[1]);
+
+            let timeout = libc::timespec {
+                tv_sec: 5,
+                tv_nsec: 0,
+            };
+            let signal = libc::sigtimedwait(&signals, ptr::null_mut(), &timeout);
+            libc::_exit(if signal == libc::SIGWINCH { 0 } else { 1 });
+        },
+        pid => {
+            unsafe {
+                libc::close(ready[1]);
+                let mut byte = 0u8;
+                libc::read(ready[0], ptr::addr_of_mut!(byte).cast(), 1);
+                libc::close(ready[0]);
+            }
+
+            terminal.set_size(SIZE).unwrap();
+            assert_eq!(terminal.size().unwrap(), SIZE);
+
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+            assert!(libc::WIFEXITED(status));
+            assert_eq!(libc::WEXITSTATUS(status), 0, "the child got SIGWINCH");
+        }
+    }
+}
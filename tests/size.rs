@@ -0,0 +1,92 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs::File;
+use std::os::fd::{AsRawFd, RawFd};
+use std::{env, io};
+
+use common::openpty;
+use terminal_utils::{SizeSource, Terminal, TerminalSize};
+
+const DEFAULT: TerminalSize = TerminalSize {
+    width: 80,
+    height: 24,
+    pixel_width: 0,
+    pixel_height: 0,
+};
+
+/// Forks a child without a controlling terminal, with `stderr` as its standard error and
+/// `/dev/null` as its other standard streams. Returns what `size_with_fallback` found there.
+fn size_without_tty(stderr: RawFd) -> (TerminalSize, SizeSource) {
+    let null = File::open("/dev/null").unwrap();
+    let mut pipe = [-1; 2];
+    assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+
+    match unsafe { libc::fork() } {
+        -1 => panic!("fork: {}", io::Error::last_os_error()),
+        0 => unsafe {
+            libc::setsid();
+            libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO);
+            libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO);
+            libc::dup2(stderr, libc::STDERR_FILENO);
+
+            let (size, source) = terminal_utils::size_with_fallback(DEFAULT);
+            let message = [size.width, size.height, source as u16];
+            libc::write(pipe[1], message.as_ptr().cast(), 6);
+            libc::_exit(0);
+        },
+        pid => {
+            let mut message = [0u16; 3];
+            unsafe {
+                libc::close(pipe[1]);
+                assert_eq!(libc::read(pipe[0], message.as_mut_ptr().cast(), 6), 6);
+                libc::close(pipe[0]);
+                libc::waitpid(pid, std::ptr::null_mut(), 0);
+            }
+
+            let size = TerminalSize {
+                width: message[0],
+                height: message[1],
+                ..DEFAULT
+            };
+            let source = [
+                SizeSource::Tty,
+                SizeSource::Stdout,
+                SizeSource::Stderr,
+                SizeSource::Stdin,
+                SizeSource::Environment,
+                SizeSource::Default,
+            ][usize::from(message[2])];
+            (size, source)
+        }
+    }
+}
+
+#[test]
+fn falls_back_without_a_controlling_terminal() {
+    let (_master, slave) = openpty();
+    let size = TerminalSize {
+        width: 120,
+        height: 40,
+        ..DEFAULT
+    };
+    Terminal::from_fd(&slave).unwrap().set_size(size).unwrap();
+
+    env::remove_var("COLUMNS");
+    env::remove_var("LINES");
+    let null = File::open("/dev/null").unwrap();
+
+    let (size, source) = size_without_tty(slave.as_raw_fd());
+    assert_eq!(source, SizeSource::Stderr);
+    assert_eq!((size.width, size.height), (120, 40));
+
+    let (size, source) = size_without_tty(null.as_raw_fd());
+    assert_eq!(source, SizeSource::Default);
+    assert_eq!(size, DEFAULT);
+
+    env::set_var("COLUMNS", "100");
+    let (size, source) = size_without_tty(null.as_raw_fd());
+    assert_eq!(source, SizeSource::Environment);
+    assert_eq!((size.width, size.height), (100, 24));
+}
@@ -0,0 +1,70 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::openpty;
+use terminal_utils::{SnapshotChange, Terminal, TermiosSnapshot};
+
+#[test]
+fn display_parses_back_to_the_same_settings() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let guard = terminal.enable_raw_mode().unwrap();
+    let snapshot = terminal.snapshot().unwrap();
+    drop(guard);
+
+    let parsed: TermiosSnapshot = snapshot.to_string().parse().unwrap();
+    assert_eq!(parsed.diff(&snapshot), []);
+    assert_eq!(parsed.to_string(), snapshot.to_string());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn parses_the_output_of_stty() {
+    let stty = "\
+speed 38400 baud; rows 24; columns 80; line = 0;
+intr = ^C; quit = ^\\; erase = ^?; kill = ^U; eof = ^D; eol = <undef>; eol2 = <undef>; swtch = <undef>; start = ^Q; stop = ^S; susp = ^Z; rprnt = ^R; werase = ^W; lnext = ^V; discard = ^O; min = 1; time = 0;
+-parenb -parodd -cmspar cs8 -hupcl -cstopb cread -clocal -crtscts
+-ignbrk -brkint -ignpar -parmrk -inpck -istrip -inlcr -igncr icrnl ixon -ixoff -iuclc -ixany -imaxbel -iutf8
+opost -olcuc -ocrnl onlcr -onocr -onlret -ofill -ofdel nl0 cr0 tab0 bs0 vt0 ff0
+isig icanon iexten echo echoe echok -echonl -noflsh -xcase -tostop -echoprt echoctl echoke -flusho -extproc
+";
+
+    let snapshot: TermiosSnapshot = stty.parse().unwrap();
+    let termios = snapshot.to_termios();
+    assert_eq!(snapshot.output_speed(), 38400);
+    assert_eq!(termios.c_cc[libc::VINTR], 3);
+    assert_eq!(termios.c_cc[libc::VQUIT], 0x1c);
+    assert_eq!(termios.c_cc[libc::VERASE], 0x7f);
+    assert_eq!(termios.c_cflag & libc::CSIZE, libc::CS8);
+    assert_ne!(termios.c_lflag & libc::ICANON, 0);
+    assert_eq!(termios.c_iflag & libc::IXOFF, 0);
+
+    let without_rows = stty.replace(" rows 24; columns 80;", "");
+    assert_eq!(snapshot.to_string(), without_rows);
+}
+
+#[test]
+fn diff_lists_the_changed_settings() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    let before = terminal.snapshot().unwrap();
+
+    let mut termios = before.to_termios();
+    termios.c_lflag &= !libc::ECHO;
+    termios.c_cflag = termios.c_cflag & !libc::CSIZE | libc::CS7;
+    termios.c_cc[libc::VINTR] = 0x18;
+    let after = TermiosSnapshot::from_termios(termios);
+
+    let changes = before.diff(&after);
+    let rendered: Vec<String> = changes.iter().map(ToString::to_string).collect();
+    assert_eq!(rendered, ["intr = ^C -> ^X", "cs8 -> cs7", "-echo"]);
+    assert_eq!(
+        changes[2],
+        SnapshotChange::Flag {
+            name: "echo",
+            enabled: false
+        }
+    );
+}
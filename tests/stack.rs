@@ -0,0 +1,47 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use common::openpty;
+use terminal_utils::{Terminal, TerminalMode};
+
+static OUT_OF_ORDER: AtomicUsize = AtomicUsize::new(0);
+
+#[test]
+fn out_of_order_drop_keeps_the_newer_mode_and_reports_it() {
+    terminal_utils::set_out_of_order_hook(|_| {
+        OUT_OF_ORDER.fetch_add(1, Ordering::SeqCst);
+    });
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let cbreak_mode_guard = terminal.enable_cbreak_mode().unwrap();
+    let raw_mode_guard = terminal.enable_raw_mode().unwrap();
+
+    drop(cbreak_mode_guard);
+    assert_eq!(OUT_OF_ORDER.load(Ordering::SeqCst), 1);
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Raw);
+
+    drop(raw_mode_guard);
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+}
+
+#[test]
+fn nested_guards_restore_the_mode_before_each_guard() {
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+
+    let no_echo_guard = terminal.enable_no_echo_mode().unwrap();
+    let cbreak_mode_guard = terminal.enable_cbreak_mode().unwrap();
+    let raw_mode_guard = terminal.enable_raw_mode().unwrap();
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Raw);
+
+    drop(raw_mode_guard);
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Cbreak);
+    drop(cbreak_mode_guard);
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::NoEcho);
+    drop(no_echo_guard);
+    assert_eq!(terminal.current_mode().unwrap(), TerminalMode::Normal);
+}
@@ -0,0 +1,64 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::ffi::CStr;
+use std::fs;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+use common::openpty;
+use terminal_utils::Terminal;
+
+#[test]
+fn refuses_a_state_directory_that_others_can_write_to() {
+    let runtime_dir =
+        std::env::temp_dir().join(format!("terminal-utils-test-shared-{}", std::process::id()));
+    let directory = runtime_dir.join("terminal-utils");
+    fs::create_dir_all(&directory).unwrap();
+    fs::set_permissions(&directory, fs::Permissions::from_mode(0o777)).unwrap();
+
+    let (_master, slave) = openpty();
+    let path = unsafe { CStr::from_ptr(libc::ttyname(slave.as_raw_fd())) }
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let planted = directory.join(path.trim_start_matches("/dev/").replace('/', "-"));
+    fs::write(&planted, "00\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_terminal-utils"))
+        .args(["recover", &path])
+        .env("XDG_RUNTIME_DIR", &runtime_dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("not a private directory"),
+        "stderr: {stderr}"
+    );
+    assert!(planted.exists());
+
+    fs::remove_dir_all(runtime_dir).unwrap();
+}
+
+#[test]
+fn raw_mode_works_when_the_state_cannot_be_saved() {
+    // a file where the runtime directory should be, so even root cannot save the state
+    let runtime_dir = std::env::temp_dir().join(format!(
+        "terminal-utils-test-not-a-directory-{}",
+        std::process::id()
+    ));
+    fs::write(&runtime_dir, "").unwrap();
+    std::env::set_var("XDG_RUNTIME_DIR", &runtime_dir);
+    terminal_utils::set_persist_state(true);
+
+    let (_master, slave) = openpty();
+    let terminal = Terminal::from_fd(&slave).unwrap();
+    let raw_mode_guard = terminal.enable_raw_mode().unwrap();
+    assert!(terminal.is_raw_mode_enabled().unwrap());
+    raw_mode_guard.restore().unwrap();
+
+    fs::remove_file(runtime_dir).unwrap();
+}